@@ -0,0 +1,547 @@
+//! APEX motion features running on the on-chip DMP.
+
+use core::convert::Infallible;
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+use crate::{
+    DataRate, DmpDataRate, Error, Icm42605, InterruptPin, MountingOrientation, field_sets,
+};
+
+/// Time without motion after which the raise-to-wake feature reports sleep (`SLEEP_TIME_OUT`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SleepTimeout {
+    Secs1x28,
+    Secs2x56,
+    Secs3x84,
+    Secs5x12,
+    #[default]
+    Secs6x4,
+    Secs7x68,
+    Secs8x96,
+    Secs10x24,
+}
+
+impl SleepTimeout {
+    /// The timeout in milliseconds.
+    pub const fn millis(self) -> u32 {
+        (self as u32 + 1) * 1280
+    }
+}
+
+/// Time the device must be held still in the sleep position before sleep is reported
+/// (`SLEEP_GESTURE_DELAY`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SleepGestureDelay {
+    Secs0x32,
+    Secs0x64,
+    Secs0x96,
+    Secs1x28,
+    #[default]
+    Secs1x6,
+    Secs1x92,
+    Secs2x24,
+    Secs2x56,
+}
+
+impl SleepGestureDelay {
+    /// The delay in milliseconds.
+    pub const fn millis(self) -> u32 {
+        (self as u32 + 1) * 320
+    }
+}
+
+/// Orientation of the chip axes relative to the device frame used by the DMP (`MOUNTING_MATRIX`).
+///
+/// Each variant names the device-frame axes that the chip X, Y and Z axes map onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum MountingMatrix {
+    /// `[1 0 0; 0 1 0; 0 0 1]`
+    #[default]
+    XYZ,
+    /// `[1 0 0; 0 -1 0; 0 0 -1]`
+    XNegYNegZ,
+    /// `[-1 0 0; 0 1 0; 0 0 -1]`
+    NegXYNegZ,
+    /// `[-1 0 0; 0 -1 0; 0 0 1]`
+    NegXNegYZ,
+    /// `[0 1 0; 1 0 0; 0 0 -1]`
+    YXNegZ,
+    /// `[0 1 0; -1 0 0; 0 0 1]`
+    YNegXZ,
+    /// `[0 -1 0; 1 0 0; 0 0 1]`
+    NegYXZ,
+    /// `[0 -1 0; -1 0 0; 0 0 -1]`
+    NegYNegXNegZ,
+}
+
+impl MountingMatrix {
+    pub(crate) const ALL: [Self; 8] = [
+        Self::XYZ,
+        Self::XNegYNegZ,
+        Self::NegXYNegZ,
+        Self::NegXNegYZ,
+        Self::YXNegZ,
+        Self::YNegXZ,
+        Self::NegYXZ,
+        Self::NegYNegXNegZ,
+    ];
+}
+
+/// Time the device must remain tilted before a tilt is reported (`TILT_WAIT_TIME_SEL`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum TiltWaitTime {
+    Secs0,
+    Secs2,
+    #[default]
+    Secs4,
+    Secs6,
+}
+
+impl TiltWaitTime {
+    /// The wait time in seconds.
+    pub const fn secs(self) -> u32 {
+        self as u32 * 2
+    }
+
+    /// The wait time in DMP samples at `odr`, or `None` for reserved rates.
+    pub const fn samples(self, odr: DmpDataRate) -> Option<u32> {
+        match odr.hz() {
+            Some(hz) => Some(self.secs() * hz as u32),
+            None => None,
+        }
+    }
+}
+
+/// Raise-to-wake / sleep gesture configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RaiseToWake {
+    pub sleep_timeout: SleepTimeout,
+    pub sleep_gesture_delay: SleepGestureDelay,
+    /// How the sensor is mounted; gestures are detected in its body frame. Only the
+    /// orientations with an equivalent [`MountingMatrix`] are supported.
+    pub orientation: MountingOrientation,
+    /// Interrupt pin that wake and sleep events are routed to, if any.
+    pub interrupt: Option<InterruptPin>,
+}
+
+impl Default for RaiseToWake {
+    fn default() -> Self {
+        Self {
+            sleep_timeout: SleepTimeout::default(),
+            sleep_gesture_delay: SleepGestureDelay::default(),
+            orientation: MountingOrientation::IDENTITY,
+            interrupt: Some(InterruptPin::Int1),
+        }
+    }
+}
+
+/// A gesture reported by the raise-to-wake feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RaiseToWakeEvent {
+    Wake,
+    Sleep,
+}
+
+/// A tilt reported by the tilt detector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TiltEvent;
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Enable the raise-to-wake / sleep gesture detector.
+    ///
+    /// The accelerometer and DMP are switched to 50 Hz. The accelerometer must be powered
+    /// (low-power or low-noise mode) for gestures to be detected.
+    ///
+    /// The DMP mounting matrix is programmed from [`RaiseToWake::orientation`]. Fails with
+    /// [`Error::InvalidConfig`] if the DMP has no equivalent matrix.
+    pub async fn enable_raise_to_wake(
+        &mut self,
+        config: RaiseToWake,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I::Error>> {
+        let matrix = config
+            .orientation
+            .mounting_matrix()
+            .ok_or(Error::InvalidConfig)?;
+        self.reset_dmp(delay).await?;
+        self.user_bank_4()
+            .apex_config_5()
+            .modify_async(|r| r.set_mounting_matrix(matrix as u8))
+            .await?;
+        self.user_bank_4()
+            .apex_config_4()
+            .modify_async(|r| r.set_sleep_time_out(config.sleep_timeout as u8))
+            .await?;
+        self.user_bank_4()
+            .apex_config_6()
+            .modify_async(|r| r.set_sleep_gesture_delay(config.sleep_gesture_delay as u8))
+            .await?;
+
+        self.start_apex(delay, |r| r.set_r_2_w_en(true)).await?;
+
+        self.user_bank_4()
+            .int_source_6()
+            .modify_async(|r| {
+                let en = config.interrupt == Some(InterruptPin::Int1);
+                r.set_wake_det_int_1_en(en);
+                r.set_sleep_det_int_1_en(en);
+            })
+            .await?;
+        self.user_bank_4()
+            .int_source_7()
+            .modify_async(|r| {
+                let en = config.interrupt == Some(InterruptPin::Int2);
+                r.set_wake_det_int_2_en(en);
+                r.set_sleep_det_int_2_en(en);
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Disable the raise-to-wake / sleep gesture detector and its interrupts.
+    pub async fn disable_raise_to_wake(&mut self) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .apex_config()
+            .modify_async(|r| r.set_r_2_w_en(false))
+            .await?;
+        self.user_bank_4()
+            .int_source_6()
+            .modify_async(|r| {
+                r.set_wake_det_int_1_en(false);
+                r.set_sleep_det_int_1_en(false);
+            })
+            .await?;
+        self.user_bank_4()
+            .int_source_7()
+            .modify_async(|r| {
+                r.set_wake_det_int_2_en(false);
+                r.set_sleep_det_int_2_en(false);
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Read and clear `INT_STATUS3`, returning the raise-to-wake gesture it reports, if any.
+    ///
+    /// Reading the status clears all other APEX interrupt flags as well.
+    pub async fn read_raise_to_wake_event(
+        &mut self,
+    ) -> Result<Option<RaiseToWakeEvent>, Error<I::Error>> {
+        let status = self.user_bank_0().int_status_3().read_async().await?;
+        Ok(if status.wake_int() {
+            Some(RaiseToWakeEvent::Wake)
+        } else if status.sleep_int() {
+            Some(RaiseToWakeEvent::Sleep)
+        } else {
+            None
+        })
+    }
+
+    /// Wait on the interrupt pin configured in [`RaiseToWake::interrupt`] for the next gesture.
+    pub async fn wait_for_raise_to_wake_event<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<RaiseToWakeEvent, Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        loop {
+            self.wait_for_interrupt(int, pin).await?;
+            if let Some(event) = self.read_raise_to_wake_event().await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Enable the tilt detector, routing its interrupt to `interrupt` if given.
+    ///
+    /// The accelerometer and DMP are switched to 50 Hz. The accelerometer must be powered
+    /// (low-power or low-noise mode) for tilts to be detected.
+    pub async fn enable_tilt(
+        &mut self,
+        wait: TiltWaitTime,
+        interrupt: Option<InterruptPin>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I::Error>> {
+        self.reset_dmp(delay).await?;
+        self.user_bank_4()
+            .apex_config_4()
+            .modify_async(|r| r.set_tilt_wait_time_sel(wait as u8))
+            .await?;
+
+        self.start_apex(delay, |r| r.set_tilt_enable(true)).await?;
+
+        self.user_bank_4()
+            .int_source_6()
+            .modify_async(|r| r.set_tilt_det_int_1_en(interrupt == Some(InterruptPin::Int1)))
+            .await?;
+        self.user_bank_4()
+            .int_source_7()
+            .modify_async(|r| r.set_tilt_det_int_2_en(interrupt == Some(InterruptPin::Int2)))
+            .await?;
+        Ok(())
+    }
+
+    /// Disable the tilt detector and its interrupts.
+    pub async fn disable_tilt(&mut self) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .apex_config()
+            .modify_async(|r| r.set_tilt_enable(false))
+            .await?;
+        self.user_bank_4()
+            .int_source_6()
+            .modify_async(|r| r.set_tilt_det_int_1_en(false))
+            .await?;
+        self.user_bank_4()
+            .int_source_7()
+            .modify_async(|r| r.set_tilt_det_int_2_en(false))
+            .await?;
+        Ok(())
+    }
+
+    /// Read and clear `INT_STATUS3`, returning whether a tilt was detected.
+    ///
+    /// Reading the status clears all other APEX interrupt flags as well.
+    pub async fn read_tilt_event(&mut self) -> Result<Option<TiltEvent>, Error<I::Error>> {
+        let status = self.user_bank_0().int_status_3().read_async().await?;
+        Ok(status.tilt_det_int().then_some(TiltEvent))
+    }
+
+    /// Wait on the interrupt pin passed to [`Self::enable_tilt`] for the next tilt.
+    pub async fn wait_for_tilt_event<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<TiltEvent, Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        loop {
+            self.wait_for_interrupt(int, pin).await?;
+            if let Some(event) = self.read_tilt_event().await? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Switch the accelerometer to 50 Hz and reset the DMP memory.
+    ///
+    /// The APEX feature configuration in bank 4 must be written after this and before
+    /// [`Self::start_apex`].
+    pub(crate) async fn reset_dmp(
+        &mut self,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .accel_config_0()
+            .modify_async(|r| r.set_gyro_odr(DataRate::Hz50))
+            .await?;
+        delay.delay_ms(1).await;
+
+        self.user_bank_0()
+            .signal_path_reset()
+            .write_async(|r| r.set_dmp_mem_reset_en(true))
+            .await?;
+        delay.delay_ms(1).await;
+        Ok(())
+    }
+
+    /// Start the DMP at 50 Hz with the APEX features selected by `enable`, after
+    /// [`Self::reset_dmp`].
+    pub(crate) async fn start_apex(
+        &mut self,
+        delay: &mut impl DelayNs,
+        enable: impl FnOnce(&mut field_sets::ApexConfig),
+    ) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .apex_config()
+            .modify_async(|r| {
+                r.set_dmp_odr(DmpDataRate::Hz50);
+                enable(r);
+            })
+            .await?;
+        self.init_dmp(delay).await
+    }
+
+    /// Initialize the DMP with the APEX configuration currently in the registers.
+    pub(crate) async fn init_dmp(
+        &mut self,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .signal_path_reset()
+            .write_async(|r| r.set_dmp_init_en(true))
+            .await?;
+        delay.delay_ms(50).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{Direction, Transaction};
+    use crate::registers::{INT_STATUS3, SIGNAL_PATH_RESET};
+    use crate::sim::{Simulator, Stationary};
+
+    /// Position of the first write matching `f` in the register log.
+    fn position(log: &[Transaction], f: impl Fn(&Transaction) -> bool) -> usize {
+        log.iter()
+            .position(|t| t.direction == Direction::Write && f(t))
+            .unwrap()
+    }
+
+    fn is_signal_path_reset(t: &Transaction, f: fn(field_sets::SignalPathReset) -> bool) -> bool {
+        u16::from_be_bytes([t.bank, t.register]) == SIGNAL_PATH_RESET
+            && f(field_sets::SignalPathReset::from([t.data[0]]))
+    }
+
+    #[test]
+    fn apex_config_is_written_between_dmp_reset_and_init() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut delay = sim.delay();
+
+        block_on(device.enable_raise_to_wake(RaiseToWake::default(), &mut delay)).unwrap();
+        block_on(device.enable_tilt(TiltWaitTime::Secs2, None, &mut delay)).unwrap();
+
+        sim.with_registers(|mock| {
+            let log = mock.transactions();
+            let mut start = 0;
+            for _ in 0..2 {
+                let log = &log[start..];
+                let reset = position(log, |t| is_signal_path_reset(t, |r| r.dmp_mem_reset_en()));
+                let config = position(log, |t| t.bank == 4 && (0x43..=0x45).contains(&t.register));
+                let init = position(log, |t| is_signal_path_reset(t, |r| r.dmp_init_en()));
+                assert!(reset < config && config < init);
+                start += init + 1;
+            }
+        });
+    }
+
+    #[test]
+    fn raise_to_wake_routes_and_clears_its_interrupts() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let config = RaiseToWake {
+            sleep_timeout: SleepTimeout::Secs2x56,
+            interrupt: Some(InterruptPin::Int2),
+            ..RaiseToWake::default()
+        };
+
+        block_on(device.enable_raise_to_wake(config, &mut sim.delay())).unwrap();
+        let apex_config = device.user_bank_0().apex_config().read().unwrap();
+        assert!(apex_config.r_2_w_en());
+        assert_eq!(apex_config.dmp_odr(), DmpDataRate::Hz50);
+        let apex_config_4 = device.user_bank_4().apex_config_4().read().unwrap();
+        assert_eq!(apex_config_4.sleep_time_out(), SleepTimeout::Secs2x56 as u8);
+        let source_6 = device.user_bank_4().int_source_6().read().unwrap();
+        assert!(!source_6.wake_det_int_1_en() && !source_6.sleep_det_int_1_en());
+        let source_7 = device.user_bank_4().int_source_7().read().unwrap();
+        assert!(source_7.wake_det_int_2_en() && source_7.sleep_det_int_2_en());
+
+        block_on(device.disable_raise_to_wake()).unwrap();
+        assert!(
+            !device
+                .user_bank_0()
+                .apex_config()
+                .read()
+                .unwrap()
+                .r_2_w_en()
+        );
+        let source_7 = device.user_bank_4().int_source_7().read().unwrap();
+        assert!(!source_7.wake_det_int_2_en() && !source_7.sleep_det_int_2_en());
+    }
+
+    #[test]
+    fn raise_to_wake_events_are_read_from_int_status3() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let raise = |bits: u8| sim.with_registers(|mock| mock.poke(INT_STATUS3, &[bits]));
+
+        assert_eq!(block_on(device.read_raise_to_wake_event()), Ok(None));
+        raise(1 << 2);
+        assert_eq!(
+            block_on(device.read_raise_to_wake_event()),
+            Ok(Some(RaiseToWakeEvent::Wake))
+        );
+        raise(1 << 1);
+        assert_eq!(
+            block_on(device.read_raise_to_wake_event()),
+            Ok(Some(RaiseToWakeEvent::Sleep))
+        );
+    }
+
+    #[test]
+    fn tilt_routes_its_interrupt_and_reports_events() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+
+        block_on(device.enable_tilt(
+            TiltWaitTime::Secs6,
+            Some(InterruptPin::Int1),
+            &mut sim.delay(),
+        ))
+        .unwrap();
+        assert!(
+            device
+                .user_bank_0()
+                .apex_config()
+                .read()
+                .unwrap()
+                .tilt_enable()
+        );
+        let apex_config_4 = device.user_bank_4().apex_config_4().read().unwrap();
+        assert_eq!(
+            apex_config_4.tilt_wait_time_sel(),
+            TiltWaitTime::Secs6 as u8
+        );
+        assert!(
+            device
+                .user_bank_4()
+                .int_source_6()
+                .read()
+                .unwrap()
+                .tilt_det_int_1_en()
+        );
+
+        sim.with_registers(|mock| mock.poke(INT_STATUS3, &[1 << 3]));
+        assert_eq!(block_on(device.read_tilt_event()), Ok(Some(TiltEvent)));
+        assert_eq!(block_on(device.read_tilt_event()), Ok(None));
+
+        block_on(device.disable_tilt()).unwrap();
+        assert!(
+            !device
+                .user_bank_0()
+                .apex_config()
+                .read()
+                .unwrap()
+                .tilt_enable()
+        );
+        assert!(
+            !device
+                .user_bank_4()
+                .int_source_6()
+                .read()
+                .unwrap()
+                .tilt_det_int_1_en()
+        );
+    }
+}
@@ -0,0 +1,663 @@
+//! Redundant IMUs read in lockstep.
+
+use core::array;
+
+use device_driver::{AsyncRegisterInterface, RegisterInterface};
+use embedded_hal_async::delay::DelayNs;
+
+use crate::{
+    AxisMask, Endian, Error, FifoItem, FifoManager, FifoPacket, FifoTimestampKind, FullScale,
+    Icm42605, MountingOrientation, RawSample, ScaledSample, TimestampResolution, field_sets,
+};
+
+/// Largest deviation from the median of the array before an IMU is outvoted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Tolerance {
+    pub accel_g: f32,
+    pub gyro_dps: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            accel_g: 0.25,
+            gyro_dps: 10.0,
+        }
+    }
+}
+
+/// Health of one IMU of an [`ImuArray`] as of the last read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImuHealth {
+    /// Reading the sample failed on the bus.
+    pub bus_error: bool,
+    /// The sample deviated from the median of the array by more than the tolerance.
+    ///
+    /// With two IMUs a fault cannot be attributed, so both are outvoted when they disagree.
+    pub outvoted: bool,
+    /// Number of consecutive reads with a fault, including the last one.
+    pub consecutive_faults: u32,
+}
+
+impl ImuHealth {
+    pub const fn is_healthy(&self) -> bool {
+        !self.bus_error && !self.outvoted
+    }
+}
+
+/// The samples of all IMUs of an [`ImuArray`] from one read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ArrayReading<const N: usize> {
+    /// The sample of each IMU, or `None` if it could not be read.
+    pub samples: [Option<ScaledSample>; N],
+    /// Host time of the sample of each IMU, in microseconds: when it was read by
+    /// [`ImuArray::read`], or the common time the samples were aligned to by
+    /// [`ImuArray::read_aligned`].
+    pub timestamps_us: [u64; N],
+    pub health: [ImuHealth; N],
+}
+
+impl<const N: usize> ArrayReading<N> {
+    /// Mean host time of the samples of the IMUs that returned one.
+    pub fn timestamp_us(&self) -> Option<u64> {
+        let (sum, count) = self
+            .samples
+            .iter()
+            .zip(self.timestamps_us)
+            .filter(|(sample, _)| sample.is_some())
+            .fold((0, 0), |(sum, count), (_, t)| (sum + t, count + 1));
+        (count > 0).then(|| sum / count)
+    }
+
+    /// Time between the earliest and the latest sample of the IMUs that returned one, in
+    /// microseconds. Aligned readings have no skew.
+    pub fn skew_us(&self) -> u64 {
+        let read = || {
+            self.samples
+                .iter()
+                .zip(self.timestamps_us)
+                .filter(|(sample, _)| sample.is_some())
+                .map(|(_, t)| t)
+        };
+        let first = read().min().unwrap_or(0);
+        let last = read().max().unwrap_or(0);
+        last - first
+    }
+
+    /// The per-axis mean of the healthy IMUs.
+    pub fn average(&self) -> Option<ScaledSample> {
+        combine(&self.samples, |i| self.health[i].is_healthy(), mean)
+    }
+
+    /// The per-axis median of all IMUs that returned a sample, outvoted or not.
+    ///
+    /// With three IMUs this is the median of three, which masks any single fault.
+    pub fn median(&self) -> Option<ScaledSample> {
+        combine(&self.samples, |_| true, median)
+    }
+
+    /// The per-axis mean of the healthy IMUs, if they are a majority of the array.
+    pub fn voted(&self) -> Option<ScaledSample> {
+        let healthy = self.health.iter().filter(|h| h.is_healthy()).count();
+        (healthy * 2 > N).then(|| self.average()).flatten()
+    }
+}
+
+/// `N` IMUs carrying redundant measurements, with `clock` returning the host time in
+/// microseconds.
+///
+/// The drivers usually share one bus. The sharing devices of `embedded-hal-bus` are blocking, so
+/// use the `_blocking` methods with them.
+///
+/// [`ImuArray::read`] fetches the data registers of all IMUs back to back, but the IMUs sample on
+/// their own clocks, so these samples are not aligned in time. [`ImuArray::read_aligned`] instead
+/// places the timestamped samples queued in their FIFOs on the host timeline and interpolates
+/// them to a common time. Each sample is reported in the body frame set with
+/// [`ImuArray::set_mounting_orientation`] for its IMU, so IMUs mounted differently can be
+/// compared. The array assumes the full-scale ranges, enabled axes, `SENSOR_DATA_ENDIAN` and
+/// `TMST_CONFIG` do not change behind its back; call [`ImuArray::sync`] after changing them.
+pub struct ImuArray<I, C, const N: usize> {
+    devices: [Icm42605<I>; N],
+    clock: C,
+    config: [ImuConfig; N],
+    tolerance: Tolerance,
+    health: [ImuHealth; N],
+    /// The previous and the latest sample of each IMU placed by [`ImuArray::read_aligned`].
+    history: [[Option<TimedSample>; 2]; N],
+}
+
+/// What an [`ImuArray`] needs to know to decode the samples of one IMU.
+#[derive(Debug, Clone, Copy)]
+struct ImuConfig {
+    endian: Endian,
+    full_scale: FullScale,
+    accel_axes: AxisMask,
+    gyro_axes: AxisMask,
+    orientation: MountingOrientation,
+    /// Resolution of the timestamps, or `None` unless they are absolute and readable through
+    /// `TMST_VAL`.
+    timestamps: Option<TimestampResolution>,
+}
+
+impl ImuConfig {
+    fn new(
+        intf: field_sets::IntfConfig0,
+        accel: field_sets::AccelConfig0,
+        gyro: field_sets::GyroConfig0,
+        sensor: field_sets::SensorConfig,
+        tmst: field_sets::TmstConfig,
+        orientation: MountingOrientation,
+    ) -> Self {
+        let (accel_axes, gyro_axes) = AxisMask::from_sensor_config(&sensor);
+        let timestamps = (tmst.tmst_en() && tmst.tmst_to_regs_en() && !tmst.tmst_delta_en())
+            .then_some(match tmst.tmst_res() {
+                true => TimestampResolution::Micros16,
+                false => TimestampResolution::Micros1,
+            });
+        Self {
+            endian: intf.sensor_data_endian(),
+            full_scale: FullScale {
+                accel: accel.gyro_fs_sel(),
+                gyro: gyro.gyro_fs_sel(),
+            },
+            accel_axes,
+            gyro_axes,
+            orientation,
+            timestamps,
+        }
+    }
+
+    fn decode(&self, sample: RawSample) -> ScaledSample {
+        let sample = sample.with_axes(self.accel_axes, self.gyro_axes);
+        self.orientation
+            .apply_raw_sample(&sample)
+            .scaled(self.full_scale)
+    }
+
+    fn decode_packet(&self, packet: &FifoPacket) -> ScaledSample {
+        let packet = packet.with_axes(self.accel_axes, self.gyro_axes);
+        let packet = self.orientation.apply_packet(&packet);
+        ScaledSample {
+            accel: self.full_scale.accel_g(packet.accel),
+            gyro: self.full_scale.gyro_dps(packet.gyro),
+            temperature: packet.temperature_celsius(),
+        }
+    }
+}
+
+/// A sample placed on the host timeline.
+#[derive(Debug, Clone, Copy)]
+struct TimedSample {
+    time_us: u64,
+    sample: ScaledSample,
+}
+
+impl<I, C: FnMut() -> u64, const N: usize> ImuArray<I, C, N> {
+    fn unsynced(devices: [Icm42605<I>; N], clock: C) -> Self {
+        let config = ImuConfig {
+            endian: Endian::BigEndian,
+            full_scale: FullScale::default(),
+            accel_axes: AxisMask::ALL,
+            gyro_axes: AxisMask::ALL,
+            orientation: MountingOrientation::IDENTITY,
+            timestamps: None,
+        };
+        Self {
+            devices,
+            clock,
+            config: [config; N],
+            tolerance: Tolerance::default(),
+            health: [ImuHealth::default(); N],
+            history: [[None; 2]; N],
+        }
+    }
+
+    pub fn devices(&mut self) -> &mut [Icm42605<I>; N] {
+        &mut self.devices
+    }
+
+    /// The mounting orientation of every IMU.
+    pub fn mounting_orientations(&self) -> [MountingOrientation; N] {
+        self.config.map(|config| config.orientation)
+    }
+
+    /// Set how IMU `index` is mounted, so that its samples are reported in its body frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not less than `N`.
+    pub fn set_mounting_orientation(&mut self, index: usize, orientation: MountingOrientation) {
+        self.config[index].orientation = orientation;
+    }
+
+    pub fn health(&self) -> &[ImuHealth; N] {
+        &self.health
+    }
+
+    pub fn tolerance(&self) -> Tolerance {
+        self.tolerance
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: Tolerance) {
+        self.tolerance = tolerance;
+    }
+
+    /// Release the drivers and the clock.
+    pub fn free(self) -> ([Icm42605<I>; N], C) {
+        (self.devices, self.clock)
+    }
+
+    /// Vote on the samples of one read and update the health flags.
+    fn vote(
+        &mut self,
+        samples: [Option<ScaledSample>; N],
+        timestamps_us: [u64; N],
+    ) -> ArrayReading<N> {
+        let median = combine(&samples, |_| true, median);
+        for (health, sample) in self.health.iter_mut().zip(&samples) {
+            health.bus_error = sample.is_none();
+            health.outvoted = match (sample, &median) {
+                (Some(sample), Some(median)) => deviates(sample, median, self.tolerance),
+                _ => false,
+            };
+            health.consecutive_faults = match health.is_healthy() {
+                true => 0,
+                false => health.consecutive_faults.saturating_add(1),
+            };
+        }
+
+        ArrayReading {
+            samples,
+            timestamps_us,
+            health: self.health,
+        }
+    }
+}
+
+impl<I, C, const N: usize> ImuArray<I, C, N>
+where
+    I: AsyncRegisterInterface<AddressType = u16>,
+    C: FnMut() -> u64,
+{
+    pub async fn new(devices: [Icm42605<I>; N], clock: C) -> Result<Self, Error<I::Error>> {
+        let mut array = Self::unsynced(devices, clock);
+        array.sync().await?;
+        Ok(array)
+    }
+
+    /// Re-read the data format, full-scale ranges and enabled axes of every IMU.
+    pub async fn sync(&mut self) -> Result<(), Error<I::Error>> {
+        for (device, config) in self.devices.iter_mut().zip(&mut self.config) {
+            *config = ImuConfig::new(
+                device.user_bank_0().intf_config_0().read_async().await?,
+                device.user_bank_0().accel_config_0().read_async().await?,
+                device.user_bank_0().gyro_config_0().read_async().await?,
+                device.user_bank_1().sensor_config().read_async().await?,
+                device.user_bank_0().tmst_config().read_async().await?,
+                config.orientation,
+            );
+        }
+        Ok(())
+    }
+
+    /// Read the latest sample of every IMU and vote on them.
+    ///
+    /// Each sample is the latest in the data registers of its IMU when read, so on top of
+    /// [`ArrayReading::skew_us`] they can be up to one sample period apart; use
+    /// [`ImuArray::read_aligned`] to align them. Bus errors are reported through the health flags
+    /// rather than failing the read.
+    pub async fn read(&mut self) -> ArrayReading<N> {
+        let mut samples = [None; N];
+        let mut timestamps_us = [0; N];
+        for (i, device) in self.devices.iter_mut().enumerate() {
+            let config = &self.config[i];
+            timestamps_us[i] = (self.clock)();
+            let read = device.read_raw_sample(config.endian).await;
+            samples[i] = read.ok().map(|sample| config.decode(sample));
+        }
+        self.vote(samples, timestamps_us)
+    }
+
+    /// Read the samples queued in the FIFO of every IMU and vote on them at a common time.
+    ///
+    /// The FIFO of each IMU is drained into `buf` through the manager at the same index of
+    /// `fifos`, whose mounting orientation should be left at the identity. The FIFOs must hold
+    /// packets with both sensors, which carry the time of their sample, and the timestamp counter
+    /// must be absolute and readable through `TMST_VAL`, as set up by
+    /// [`Icm42605::configure_timestamp`] with `fifo_delta` off. Strobing the counter right after
+    /// draining gives the age of each sample, which places it on the host timeline of the clock.
+    /// The 16-bit FIFO timestamps wrap, so every FIFO must be read at least once per 65 ms at
+    /// 1 µs resolution.
+    ///
+    /// The samples of all IMUs are linearly interpolated to the latest time reached by all of
+    /// them, the time of the latest sample of the IMU sampled least recently, assuming they run
+    /// at the same data rate. An IMU that failed to read, or has not yet queued a sample, is
+    /// reported like a failed read of [`ImuArray::read`].
+    ///
+    /// Fails with [`Error::InvalidConfig`] if an IMU did not have timestamps set up as above when
+    /// last synced.
+    pub async fn read_aligned<D: DelayNs>(
+        &mut self,
+        fifos: &mut [FifoManager<D>; N],
+        buf: &mut [u8],
+    ) -> Result<ArrayReading<N>, Error<I::Error>> {
+        if self.config.iter().any(|config| config.timestamps.is_none()) {
+            return Err(Error::InvalidConfig);
+        }
+        let mut read = [false; N];
+        for (i, (device, fifo)) in self.devices.iter_mut().zip(fifos).enumerate() {
+            let config = &self.config[i];
+            // Only the two most recent samples are needed for interpolation.
+            let mut latest: [Option<(u16, ScaledSample)>; 2] = [None; 2];
+            let drained = fifo
+                .drain(device, buf, |item| {
+                    if let FifoItem::Packet(packet) = item
+                        && packet.header.timestamp() == FifoTimestampKind::Odr
+                        && let Some(timestamp) = packet.timestamp
+                    {
+                        latest = [latest[1], Some((timestamp, config.decode_packet(&packet)))];
+                    }
+                })
+                .await;
+            let host_us = (self.clock)();
+            let Ok(now) = (match drained {
+                Ok(_) => device.read_tmst_val().await,
+                Err(e) => Err(e),
+            }) else {
+                continue;
+            };
+            read[i] = true;
+
+            let tick_us = config.timestamps.map_or(1, |res| u64::from(res.micros()));
+            let history = &mut self.history[i];
+            for (timestamp, sample) in latest.into_iter().flatten() {
+                // FIFO timestamps hold the low bits of the counter.
+                let age_us = u64::from((now as u16).wrapping_sub(timestamp)) * tick_us;
+                let time_us = host_us.saturating_sub(age_us);
+                *history = [history[1], Some(TimedSample { time_us, sample })];
+            }
+        }
+
+        let reference_us = (0..N)
+            .filter(|&i| read[i])
+            .filter_map(|i| self.history[i][1])
+            .map(|latest| latest.time_us)
+            .min();
+        let samples = array::from_fn(|i| {
+            let time_us = reference_us.filter(|_| read[i])?;
+            sample_at(&self.history[i], time_us)
+        });
+        Ok(self.vote(samples, [reference_us.unwrap_or(0); N]))
+    }
+}
+
+impl<I, C, const N: usize> ImuArray<I, C, N>
+where
+    I: RegisterInterface<AddressType = u16>,
+    C: FnMut() -> u64,
+{
+    /// Like [`ImuArray::new`], for drivers on a blocking bus.
+    pub fn new_blocking(devices: [Icm42605<I>; N], clock: C) -> Result<Self, Error<I::Error>> {
+        let mut array = Self::unsynced(devices, clock);
+        array.sync_blocking()?;
+        Ok(array)
+    }
+
+    /// Like [`ImuArray::sync`], for drivers on a blocking bus.
+    pub fn sync_blocking(&mut self) -> Result<(), Error<I::Error>> {
+        for (device, config) in self.devices.iter_mut().zip(&mut self.config) {
+            *config = ImuConfig::new(
+                device.user_bank_0().intf_config_0().read()?,
+                device.user_bank_0().accel_config_0().read()?,
+                device.user_bank_0().gyro_config_0().read()?,
+                device.user_bank_1().sensor_config().read()?,
+                device.user_bank_0().tmst_config().read()?,
+                config.orientation,
+            );
+        }
+        Ok(())
+    }
+
+    /// Like [`ImuArray::read`], for drivers on a blocking bus.
+    pub fn read_blocking(&mut self) -> ArrayReading<N> {
+        let mut samples = [None; N];
+        let mut timestamps_us = [0; N];
+        for (i, device) in self.devices.iter_mut().enumerate() {
+            let config = &self.config[i];
+            timestamps_us[i] = (self.clock)();
+            let read = device.read_raw_sample_blocking(config.endian);
+            samples[i] = read.ok().map(|sample| config.decode(sample));
+        }
+        self.vote(samples, timestamps_us)
+    }
+}
+
+/// The value at `time_us` of the previous and the latest sample in `history`, interpolated
+/// between them or else the nearest one.
+fn sample_at(history: &[Option<TimedSample>; 2], time_us: u64) -> Option<ScaledSample> {
+    match *history {
+        [Some(previous), Some(latest)] if time_us < latest.time_us => {
+            let Some(elapsed) = time_us.checked_sub(previous.time_us) else {
+                return Some(previous.sample);
+            };
+            let t = elapsed as f32 / (latest.time_us - previous.time_us) as f32;
+            Some(interpolate(&previous.sample, &latest.sample, t))
+        }
+        [_, latest] => latest.map(|latest| latest.sample),
+    }
+}
+
+/// Linear interpolation from `a` at `t = 0` to `b` at `t = 1`.
+fn interpolate(a: &ScaledSample, b: &ScaledSample, t: f32) -> ScaledSample {
+    let mix = |a: f32, b: f32| a + (b - a) * t;
+    let axes =
+        |a: [Option<f32>; 3], b: [Option<f32>; 3]| array::from_fn(|i| Some(mix(a[i]?, b[i]?)));
+    ScaledSample {
+        accel: axes(a.accel, b.accel),
+        gyro: axes(a.gyro, b.gyro),
+        temperature: mix(a.temperature, b.temperature),
+    }
+}
+
+/// Whether any axis of `sample` is further from `median` than the tolerance.
+fn deviates(sample: &ScaledSample, median: &ScaledSample, tolerance: Tolerance) -> bool {
+    let exceeds = |a: [Option<f32>; 3], b: [Option<f32>; 3], limit: f32| {
+        a.iter().zip(b).any(|(a, b)| match (a, b) {
+            (Some(a), Some(b)) => (a - b).abs() > limit,
+            _ => false,
+        })
+    };
+    exceeds(sample.accel, median.accel, tolerance.accel_g)
+        || exceeds(sample.gyro, median.gyro, tolerance.gyro_dps)
+}
+
+/// Combine each axis of the samples selected by `include` with `f`, skipping axes without data.
+fn combine<const N: usize>(
+    samples: &[Option<ScaledSample>; N],
+    include: impl Fn(usize) -> bool,
+    f: fn(&mut [f32]) -> f32,
+) -> Option<ScaledSample> {
+    let axis = |get: &dyn Fn(&ScaledSample) -> Option<f32>| {
+        let mut values = [0.0; N];
+        let mut len = 0;
+        for (i, sample) in samples.iter().enumerate() {
+            if let Some(value) = sample.as_ref().filter(|_| include(i)).and_then(get) {
+                values[len] = value;
+                len += 1;
+            }
+        }
+        (len > 0).then(|| f(&mut values[..len]))
+    };
+
+    let temperature = axis(&|s| Some(s.temperature))?;
+    Some(ScaledSample {
+        accel: [0, 1, 2].map(|a| axis(&|s| s.accel[a])),
+        gyro: [0, 1, 2].map(|a| axis(&|s| s.gyro[a])),
+        temperature,
+    })
+}
+
+fn mean(values: &mut [f32]) -> f32 {
+    values.iter().sum::<f32>() / values.len() as f32
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_unstable_by(f32::total_cmp);
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::sim::{Motion, MotionProfile, Simulator};
+    use crate::{AccelMode, FifoMode, GyroMode, MountingMatrix, TimestampConfig};
+
+    /// Acceleration along X rising by 0.1 g per millisecond of host time, on a device whose
+    /// clock is `offset_us` ahead of the host.
+    struct Ramp {
+        offset_us: u64,
+    }
+
+    impl MotionProfile for Ramp {
+        fn motion(&mut self, time_us: u64) -> Motion {
+            Motion {
+                accel: [(time_us - self.offset_us) as f32 / 10_000.0, 0.0, 1.0],
+                ..Motion::default()
+            }
+        }
+    }
+
+    #[test]
+    fn skew_ignores_failed_reads() {
+        let sample = ScaledSample {
+            accel: [Some(0.0); 3],
+            gyro: [Some(0.0); 3],
+            temperature: 25.0,
+        };
+        let reading = ArrayReading {
+            samples: [Some(sample), None, Some(sample)],
+            timestamps_us: [100, 5_000, 130],
+            health: [ImuHealth::default(); 3],
+        };
+        assert_eq!(reading.skew_us(), 30);
+        assert_eq!(reading.timestamp_us(), Some(115));
+    }
+
+    #[test]
+    fn imus_mounted_differently_agree_in_the_body_frame() {
+        let flat: fn(u64) -> Motion = |_| Motion::default();
+        let upside_down: fn(u64) -> Motion = |_| Motion {
+            accel: [0.0, 0.0, -1.0],
+            ..Motion::default()
+        };
+        let sims = [flat, upside_down, flat].map(|profile| Simulator::new(false, profile));
+        let mut devices = [0, 1, 2].map(|i| Icm42605::new(sims[i].bus()));
+        for (sim, device) in sims.iter().zip(&mut devices) {
+            device
+                .user_bank_0()
+                .pwr_mgmt()
+                .write(|r| r.set_accel_mode(AccelMode::LowNoise))
+                .unwrap();
+            sim.advance(1_000);
+        }
+
+        let mut time_us = 0;
+        let mut array = ImuArray::new_blocking(devices, || {
+            time_us += 10;
+            time_us
+        })
+        .unwrap();
+        array.set_mounting_orientation(1, MountingMatrix::NegXYNegZ.into());
+        array.sync_blocking().unwrap();
+        assert_eq!(
+            array.mounting_orientations()[1],
+            MountingMatrix::NegXYNegZ.into()
+        );
+        let reading = array.read_blocking();
+        assert!(reading.health.iter().all(ImuHealth::is_healthy));
+        assert_eq!(reading.skew_us(), 20);
+        let accel = reading.voted().unwrap().accel;
+        assert!((accel[2].unwrap() - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn aligned_readings_agree_across_offset_clocks() {
+        let offsets_us = [0, 5_000, 12_345];
+        // Host times at which the IMUs start sampling, 300 and 700 µs apart.
+        let start_us = [0, 300, 700];
+        let sims = offsets_us.map(|offset_us| Simulator::new(false, Ramp { offset_us }));
+        let mut devices = [0, 1, 2].map(|i| Icm42605::new(sims[i].bus()));
+        for device in &mut devices {
+            block_on(device.configure_timestamp(TimestampConfig::default())).unwrap();
+            device
+                .user_bank_0()
+                .fifo_config_1()
+                .write(|r| {
+                    r.set_fifo_accel_en(true);
+                    r.set_fifo_gyro_en(true);
+                })
+                .unwrap();
+            device
+                .user_bank_0()
+                .fifo_config()
+                .write(|r| r.set_fifo_mode(FifoMode::Stream))
+                .unwrap();
+        }
+        for (sim, offset_us) in sims.iter().zip(offsets_us) {
+            sim.advance(offset_us);
+        }
+        let mut fifos = [0, 1, 2]
+            .map(|i| block_on(FifoManager::new(sims[i].delay(), &mut devices[i])).unwrap());
+
+        let host_us = Cell::new(0);
+        let mut array = block_on(ImuArray::new(devices, || host_us.get())).unwrap();
+        let advance = |us: u64| {
+            host_us.set(host_us.get() + us);
+            sims.iter().for_each(|sim| sim.advance(us));
+        };
+        for (i, start_us) in start_us.into_iter().enumerate() {
+            advance(start_us - host_us.get());
+            array.devices()[i]
+                .user_bank_0()
+                .pwr_mgmt()
+                .write(|r| {
+                    r.set_accel_mode(AccelMode::LowNoise);
+                    r.set_gyro_mode(GyroMode::LowNoise);
+                })
+                .unwrap();
+        }
+        advance(10_000 - host_us.get());
+
+        // The latest samples in the data registers are from 10.0, 9.3 and 9.7 ms.
+        let latest = block_on(array.read());
+        let x = latest
+            .samples
+            .map(|sample| sample.unwrap().accel[0].unwrap());
+        assert!((x[0] - x[1]).abs() > 0.05);
+
+        let mut buf = [0; 256];
+        for host_ms in [10, 12] {
+            advance(host_ms * 1_000 - host_us.get());
+            let reading = block_on(array.read_aligned(&mut fifos, &mut buf)).unwrap();
+            assert!(reading.health.iter().all(ImuHealth::is_healthy));
+            assert_eq!(reading.skew_us(), 0);
+            let reference_us = reading.timestamp_us().unwrap();
+            assert_eq!(reference_us, host_ms * 1_000 - 700);
+            for sample in reading.samples {
+                let x = sample.unwrap().accel[0].unwrap();
+                assert!((x - reference_us as f32 / 10_000.0).abs() < 0.002);
+            }
+        }
+    }
+}
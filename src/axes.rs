@@ -0,0 +1,151 @@
+//! Per-axis sensor enables.
+
+use core::ops::BitOr;
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::{Error, Icm42605, field_sets};
+
+/// A set of X, Y and Z axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AxisMask(pub u8);
+
+impl Default for AxisMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for AxisMask {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl AxisMask {
+    pub const NONE: Self = Self(0);
+    pub const X: Self = Self(0x01);
+    pub const Y: Self = Self(0x02);
+    pub const Z: Self = Self(0x04);
+    pub const ALL: Self = Self(0x07);
+
+    pub const fn x(self) -> bool {
+        self.0 & Self::X.0 != 0
+    }
+
+    pub const fn y(self) -> bool {
+        self.0 & Self::Y.0 != 0
+    }
+
+    pub const fn z(self) -> bool {
+        self.0 & Self::Z.0 != 0
+    }
+
+    /// Whether the axis with index `axis` (0 for X, 1 for Y, 2 for Z) is in the set.
+    pub const fn contains(self, axis: usize) -> bool {
+        axis < 3 && self.0 & (1 << axis) != 0
+    }
+
+    /// Replace the values of axes not in the set with `None`.
+    pub fn mask<T>(self, values: [Option<T>; 3]) -> [Option<T>; 3] {
+        let mut axis = 0;
+        values.map(|value| {
+            let value = value.filter(|_| self.contains(axis));
+            axis += 1;
+            value
+        })
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Enable only the given accelerometer and gyroscope axes (`SENSOR_CONFIG0`).
+    ///
+    /// Data of disabled axes is not valid; apply the masks with [`FifoPacket::with_axes`] to
+    /// report such axes as `None`.
+    ///
+    /// [`FifoPacket::with_axes`]: crate::FifoPacket::with_axes
+    pub async fn enable_axes(
+        &mut self,
+        accel: AxisMask,
+        gyro: AxisMask,
+    ) -> Result<(), Error<I::Error>> {
+        self.user_bank_1()
+            .sensor_config()
+            .modify_async(|r| {
+                r.set_xa_disable(!accel.x());
+                r.set_ya_disable(!accel.y());
+                r.set_za_disable(!accel.z());
+                r.set_xg_disable(!gyro.x());
+                r.set_yg_disable(!gyro.y());
+                r.set_zg_disable(!gyro.z());
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Read the enabled accelerometer and gyroscope axes.
+    pub async fn enabled_axes(&mut self) -> Result<(AxisMask, AxisMask), Error<I::Error>> {
+        let r = self.user_bank_1().sensor_config().read_async().await?;
+        Ok(AxisMask::from_sensor_config(&r))
+    }
+}
+
+impl AxisMask {
+    /// The enabled accelerometer and gyroscope axes in `SENSOR_CONFIG0`.
+    pub(crate) fn from_sensor_config(r: &field_sets::SensorConfig) -> (Self, Self) {
+        let mask =
+            |x: bool, y: bool, z: bool| Self(u8::from(!x) | u8::from(!y) << 1 | u8::from(!z) << 2);
+        (
+            mask(r.xa_disable(), r.ya_disable(), r.za_disable()),
+            mask(r.xg_disable(), r.yg_disable(), r.zg_disable()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::registers::SENSOR_CONFIG;
+    use crate::sim::{Simulator, Stationary};
+    use crate::{AccelMode, Endian, GyroMode};
+
+    #[test]
+    fn axis_enables_round_trip_through_sensor_config() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let (accel, gyro) = (AxisMask::X | AxisMask::Z, AxisMask::Y);
+
+        block_on(device.enable_axes(accel, gyro)).unwrap();
+        let mut config = [0];
+        sim.with_registers(|mock| mock.peek(SENSOR_CONFIG, &mut config));
+        // YA_DISABLE, XG_DISABLE and ZG_DISABLE.
+        assert_eq!(config, [0b0010_1010]);
+        assert_eq!(block_on(device.enabled_axes()), Ok((accel, gyro)));
+
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| {
+                r.set_accel_mode(AccelMode::LowNoise);
+                r.set_gyro_mode(GyroMode::LowNoise);
+            })
+            .unwrap();
+        sim.advance(1_000);
+        let sample = block_on(device.read_raw_sample(Endian::BigEndian))
+            .unwrap()
+            .with_axes(accel, gyro);
+        assert!(sample.accel[0].is_some() && sample.accel[2].is_some());
+        assert_eq!(sample.accel[1], None);
+        assert!(sample.gyro[1].is_some());
+        assert_eq!((sample.gyro[0], sample.gyro[2]), (None, None));
+
+        block_on(device.enable_axes(AxisMask::ALL, AxisMask::ALL)).unwrap();
+        sim.with_registers(|mock| mock.peek(SENSOR_CONFIG, &mut config));
+        assert_eq!(config, [0]);
+    }
+}
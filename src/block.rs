@@ -0,0 +1,128 @@
+//! Multi-register transfers using the chip's address auto-increment.
+
+use device_driver::{AsyncRegisterInterface, RegisterInterface};
+
+use crate::registers::FIFO_DATA;
+use crate::{Error, Icm42605};
+
+/// Number of registers in a bank.
+pub(crate) const BANK_SIZE: usize = 256;
+
+/// The address of a block of `len` registers, or [`Error::InvalidConfig`] if it runs past the end
+/// of the bank. Reads of `FIFO_DATA` may be of any length, as its address does not increment.
+fn block_address<E>(bank: u8, start_reg: u8, len: usize, read: bool) -> Result<u16, Error<E>> {
+    let address = u16::from_be_bytes([bank, start_reg]);
+    if usize::from(start_reg) + len <= BANK_SIZE || (read && address == FIFO_DATA) {
+        Ok(address)
+    } else {
+        Err(Error::InvalidConfig)
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Read `buf.len()` bytes starting at `start_reg` in `bank` in one transaction.
+    ///
+    /// The chip increments the register address after every byte, except for `FIFO_DATA`, which
+    /// can be read repeatedly to drain the FIFO. The bank is selected first when needed. Blocks
+    /// that run past the end of the bank fail with [`Error::InvalidConfig`] without accessing
+    /// the bus.
+    pub async fn read_block(
+        &mut self,
+        bank: u8,
+        start_reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = block_address(bank, start_reg, buf.len(), true)?;
+        self.interface()
+            .read_register(address, (buf.len() * 8) as u32, buf)
+            .await?;
+        Ok(())
+    }
+
+    /// Write `data` to the registers starting at `start_reg` in `bank` in one transaction.
+    ///
+    /// Blocks that run past the end of the bank fail with [`Error::InvalidConfig`] without
+    /// accessing the bus.
+    pub async fn write_block(
+        &mut self,
+        bank: u8,
+        start_reg: u8,
+        data: &[u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = block_address(bank, start_reg, data.len(), false)?;
+        self.interface()
+            .write_register(address, (data.len() * 8) as u32, data)
+            .await?;
+        Ok(())
+    }
+}
+
+impl<I: RegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Like [`Icm42605::read_block`], for drivers on a blocking bus.
+    pub fn read_block_blocking(
+        &mut self,
+        bank: u8,
+        start_reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = block_address(bank, start_reg, buf.len(), true)?;
+        self.interface()
+            .read_register(address, (buf.len() * 8) as u32, buf)?;
+        Ok(())
+    }
+
+    /// Like [`Icm42605::write_block`], for drivers on a blocking bus.
+    pub fn write_block_blocking(
+        &mut self,
+        bank: u8,
+        start_reg: u8,
+        data: &[u8],
+    ) -> Result<(), Error<I::Error>> {
+        let address = block_address(bank, start_reg, data.len(), false)?;
+        self.interface()
+            .write_register(address, (data.len() * 8) as u32, data)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::Icm42605Interface;
+    use crate::mock::MockDevice;
+    use crate::sim::{Simulator, Stationary};
+
+    #[test]
+    fn blocks_past_the_end_of_the_bank_are_rejected() {
+        let mut mock = MockDevice::new(false);
+        {
+            let mut device = Icm42605::new(Icm42605Interface::new(&mut mock, false));
+            assert_eq!(
+                device.write_block_blocking(4, 0xff, &[0, 0]),
+                Err(Error::InvalidConfig)
+            );
+            assert_eq!(
+                device.read_block_blocking(0, 0xf0, &mut [0; 32]),
+                Err(Error::InvalidConfig)
+            );
+        }
+        assert!(mock.transactions().is_empty());
+    }
+
+    #[test]
+    fn fifo_data_reads_may_exceed_the_bank() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let [bank, reg] = FIFO_DATA.to_be_bytes();
+        assert_eq!(
+            block_on(device.read_block(bank, reg, &mut [0; BANK_SIZE])),
+            Ok(())
+        );
+        assert_eq!(
+            device.read_block_blocking(bank, reg, &mut [0; BANK_SIZE]),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Reading samples from the data registers as they become ready, without the FIFO.
+
+use core::convert::Infallible;
+
+use device_driver::{AsyncRegisterInterface, RegisterInterface};
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+use crate::registers::TEMP_DATA;
+use crate::{
+    AxisMask, Endian, Error, FullScale, Icm42605, IntClearOption, InterruptPin,
+    MountingOrientation, ScaledSample,
+};
+
+/// Length of the temperature, accelerometer and gyroscope data registers.
+pub(crate) const SENSOR_DATA_LEN: usize = 14;
+/// Interval between reads of `INT_STATUS` while polling.
+const POLL_US: u32 = 100;
+
+/// A sample read from the sensor data registers.
+///
+/// Axes that are disabled or whose sensor is off are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RawSample {
+    pub accel: [Option<i16>; 3],
+    pub gyro: [Option<i16>; 3],
+    pub temperature: i16,
+}
+
+impl RawSample {
+    /// Decode the 14 bytes starting at `TEMP_DATA1` according to `SENSOR_DATA_ENDIAN`.
+    pub fn parse(bytes: &[u8; SENSOR_DATA_LEN], endian: Endian) -> Self {
+        let word = |i: usize| {
+            let b = [bytes[i], bytes[i + 1]];
+            match endian {
+                Endian::BigEndian => i16::from_be_bytes(b),
+                Endian::LittleEndian => i16::from_le_bytes(b),
+            }
+        };
+        let axes =
+            |start: usize| [0, 2, 4].map(|i| Some(word(start + i)).filter(|&v| v != i16::MIN));
+        Self {
+            accel: axes(2),
+            gyro: axes(8),
+            temperature: word(0),
+        }
+    }
+
+    /// Report the axes disabled with [`Icm42605::enable_axes`] as `None`.
+    pub fn with_axes(self, accel: AxisMask, gyro: AxisMask) -> Self {
+        Self {
+            accel: accel.mask(self.accel),
+            gyro: gyro.mask(self.gyro),
+            ..self
+        }
+    }
+
+    /// Temperature in degrees Celsius.
+    pub fn temperature_celsius(&self) -> f32 {
+        f32::from(self.temperature) / 132.48 + 25.0
+    }
+
+    /// Convert the sample into physical units using the configured full-scale ranges.
+    pub fn scaled(&self, full_scale: FullScale) -> ScaledSample {
+        ScaledSample {
+            accel: full_scale.accel_g(self.accel),
+            gyro: full_scale.gyro_dps(self.gyro),
+            temperature: self.temperature_celsius(),
+        }
+    }
+}
+
+/// A stream of fresh samples from the data registers, signalled by the data ready interrupt.
+///
+/// A sample is only returned once `DATA_RDY_INT` reports that it is new, so the same sample is
+/// never returned twice. Checking the flag reads `INT_STATUS`, which also clears the FIFO and
+/// FSYNC interrupt flags.
+///
+/// Samples are reported in the body frame set with [`DataReadyStream::set_mounting_orientation`].
+///
+/// The stream assumes `SENSOR_DATA_ENDIAN` and `SENSOR_CONFIG0` do not change behind its back;
+/// call [`DataReadyStream::sync`] after changing them.
+#[derive(Debug)]
+pub struct DataReadyStream<D> {
+    delay: D,
+    endian: Endian,
+    accel_axes: AxisMask,
+    gyro_axes: AxisMask,
+    orientation: MountingOrientation,
+    timeout_us: u32,
+}
+
+impl<D: DelayNs> DataReadyStream<D> {
+    /// Default time [`DataReadyStream::poll`] waits for a sample.
+    pub const DEFAULT_TIMEOUT_US: u32 = 100_000;
+
+    /// Set up the data ready interrupt and route it to `interrupt` if given.
+    ///
+    /// The interrupt is cleared both by reading `INT_STATUS` and by reading the data registers.
+    pub async fn new<I>(
+        delay: D,
+        device: &mut Icm42605<I>,
+        interrupt: Option<InterruptPin>,
+    ) -> Result<Self, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        device
+            .user_bank_0()
+            .int_config_0()
+            .modify_async(|r| r.set_ui_drdy_int_clear(IntClearOption::Both))
+            .await?;
+        device
+            .user_bank_0()
+            .int_source_0()
+            .modify_async(|r| r.set_ui_drdy_int_1_en(interrupt == Some(InterruptPin::Int1)))
+            .await?;
+        device
+            .user_bank_0()
+            .int_source_3()
+            .modify_async(|r| r.set_ui_drdy_int_2_en(interrupt == Some(InterruptPin::Int2)))
+            .await?;
+
+        let mut stream = Self {
+            delay,
+            endian: Endian::BigEndian,
+            accel_axes: AxisMask::ALL,
+            gyro_axes: AxisMask::ALL,
+            orientation: MountingOrientation::IDENTITY,
+            timeout_us: Self::DEFAULT_TIMEOUT_US,
+        };
+        stream.sync(device).await?;
+        Ok(stream)
+    }
+
+    /// Re-read the data format and the enabled axes from the device.
+    pub async fn sync<I>(&mut self, device: &mut Icm42605<I>) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        self.endian = device
+            .user_bank_0()
+            .intf_config_0()
+            .read_async()
+            .await?
+            .sensor_data_endian();
+        (self.accel_axes, self.gyro_axes) = device.enabled_axes().await?;
+        Ok(())
+    }
+
+    pub fn mounting_orientation(&self) -> MountingOrientation {
+        self.orientation
+    }
+
+    /// Set how the sensor is mounted, so that samples are reported in its body frame.
+    pub fn set_mounting_orientation(&mut self, orientation: MountingOrientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn timeout_us(&self) -> u32 {
+        self.timeout_us
+    }
+
+    /// Set how long [`DataReadyStream::poll`] waits for a sample.
+    pub fn set_timeout_us(&mut self, timeout_us: u32) {
+        self.timeout_us = timeout_us;
+    }
+
+    /// Wait for the next sample on the interrupt output `int`, connected to `pin`.
+    pub async fn next<I, P>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<RawSample, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+        P: Wait<Error = Infallible>,
+    {
+        loop {
+            device.wait_for_interrupt(int, pin).await?;
+            match self.try_next(device).await {
+                // A pulse for a sample that was already read, or another interrupt source.
+                Err(Error::NotReady) => device.wait_for_interrupt_deasserted(int, pin).await?,
+                result => return result,
+            }
+        }
+    }
+
+    /// Poll `DATA_RDY_INT` until the next sample is ready.
+    ///
+    /// Fails with [`Error::Timeout`] if no sample is ready within the timeout.
+    pub async fn poll<I>(&mut self, device: &mut Icm42605<I>) -> Result<RawSample, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let mut waited = 0;
+        loop {
+            match self.try_next(device).await {
+                Err(Error::NotReady) => {}
+                result => return result,
+            }
+            if waited >= self.timeout_us {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_us(POLL_US).await;
+            waited = waited.saturating_add(POLL_US);
+        }
+    }
+
+    /// Read the next sample if one is ready, without waiting.
+    ///
+    /// Fails with [`Error::NotReady`] if no new sample is available.
+    pub async fn try_next<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+    ) -> Result<RawSample, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if !device
+            .user_bank_0()
+            .int_status()
+            .read_async()
+            .await?
+            .data_rdy_int()
+        {
+            return Err(Error::NotReady);
+        }
+        self.read(device).await
+    }
+
+    /// Release the delay provider.
+    pub fn free(self) -> D {
+        self.delay
+    }
+
+    async fn read<I>(&mut self, device: &mut Icm42605<I>) -> Result<RawSample, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let sample = device.read_raw_sample(self.endian).await?;
+        let sample = sample.with_axes(self.accel_axes, self.gyro_axes);
+        Ok(self.orientation.apply_raw_sample(&sample))
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Burst-read the data registers, which are encoded according to `endian`.
+    pub(crate) async fn read_raw_sample(
+        &mut self,
+        endian: Endian,
+    ) -> Result<RawSample, Error<I::Error>> {
+        let [bank, reg] = TEMP_DATA.to_be_bytes();
+        let mut bytes = [0; SENSOR_DATA_LEN];
+        self.read_block(bank, reg, &mut bytes).await?;
+        Ok(RawSample::parse(&bytes, endian))
+    }
+}
+
+impl<I: RegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Like [`Icm42605::read_raw_sample`], for drivers on a blocking bus.
+    pub(crate) fn read_raw_sample_blocking(
+        &mut self,
+        endian: Endian,
+    ) -> Result<RawSample, Error<I::Error>> {
+        let [bank, reg] = TEMP_DATA.to_be_bytes();
+        let mut bytes = [0; SENSOR_DATA_LEN];
+        self.read_block_blocking(bank, reg, &mut bytes)?;
+        Ok(RawSample::parse(&bytes, endian))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::sim::{Simulator, Stationary};
+    use crate::{AccelMode, MountingMatrix};
+
+    #[test]
+    fn try_next_returns_each_sample_once() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut stream = block_on(DataReadyStream::new(sim.delay(), &mut device, None)).unwrap();
+
+        assert_eq!(block_on(stream.try_next(&mut device)), Err(Error::NotReady));
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| r.set_accel_mode(AccelMode::LowNoise))
+            .unwrap();
+        sim.advance(1_000);
+        assert!(block_on(stream.try_next(&mut device)).is_ok());
+        assert_eq!(block_on(stream.try_next(&mut device)), Err(Error::NotReady));
+    }
+
+    #[test]
+    fn samples_are_reported_in_the_body_frame() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut stream = block_on(DataReadyStream::new(sim.delay(), &mut device, None)).unwrap();
+        stream.set_mounting_orientation(MountingMatrix::NegXYNegZ.into());
+        block_on(stream.sync(&mut device)).unwrap();
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| r.set_accel_mode(AccelMode::LowNoise))
+            .unwrap();
+
+        sim.advance(1_000);
+        let sample = block_on(stream.try_next(&mut device)).unwrap();
+        assert!(sample.accel[2].unwrap() < -2000);
+    }
+}
@@ -0,0 +1,43 @@
+//! Device identification and reset.
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::{Error, Icm42605};
+
+/// `WHO_AM_I` value of the ICM-42605.
+const WHO_AM_I: u8 = 0x42;
+/// Time after a soft reset before registers may be accessed.
+const RESET_US: u32 = 1_000;
+/// How often `RESET_DONE_INT` is polled after a soft reset before giving up.
+const RESET_POLLS: u32 = 10;
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Check that the device identifies as an ICM-42605.
+    pub async fn probe(&mut self) -> Result<(), Error<I::Error>> {
+        let who_am_i = self.user_bank_0().who_am_i().read_async().await?;
+        if who_am_i.whoami() != WHO_AM_I {
+            return Err(Error::InvalidDevice);
+        }
+        Ok(())
+    }
+
+    /// Reset all registers to their default values and wait for `RESET_DONE_INT`.
+    ///
+    /// Fails with [`Error::Timeout`] if the device does not report completion within about
+    /// 10 ms. Reading `INT_STATUS` clears any other pending status flags.
+    pub async fn soft_reset(&mut self, delay: &mut impl DelayNs) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .device_config()
+            .write_async(|r| r.set_soft_reset_config(true))
+            .await?;
+        for _ in 0..RESET_POLLS {
+            delay.delay_us(RESET_US).await;
+            let status = self.user_bank_0().int_status().read_async().await?;
+            if status.reset_done_int() {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+}
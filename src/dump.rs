@@ -0,0 +1,138 @@
+//! Register dumps for debugging.
+
+use core::fmt;
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::registers::MAX_SIZE_BYTES;
+use crate::{Error, Icm42605, REGISTERS, RegisterAccess, RegisterInfo, field_sets};
+
+/// The value of a single register captured by [`Icm42605::dump_registers`].
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterValue {
+    pub info: &'static RegisterInfo,
+    bytes: [u8; MAX_SIZE_BYTES],
+}
+
+impl RegisterValue {
+    pub(crate) const fn new(info: &'static RegisterInfo, bytes: [u8; MAX_SIZE_BYTES]) -> Self {
+        Self { info, bytes }
+    }
+
+    /// The raw register bytes as read from the device.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.info.size_bytes]
+    }
+
+    /// The register value decoded into its field set.
+    pub fn value(&self) -> field_sets::FieldSetValue {
+        self.info.decode(&self.bytes)
+    }
+}
+
+impl fmt::Display for RegisterValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [bank, reg] = self.info.address.to_be_bytes();
+        write!(f, "{bank}:{reg:02x} {:<26}", self.info.name)?;
+        for byte in self.bytes() {
+            write!(f, " {byte:02x}")?;
+        }
+        write!(f, "  {:?}", self.value())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RegisterValue {
+    fn format(&self, f: defmt::Formatter) {
+        let [bank, reg] = self.info.address.to_be_bytes();
+        defmt::write!(
+            f,
+            "{=u8}:{=u8:02x} {=str} {=[u8]:02x} {}",
+            bank,
+            reg,
+            self.info.name,
+            self.bytes(),
+            self.value()
+        );
+    }
+}
+
+/// A snapshot of register values, in address order.
+///
+/// Formatting with `{}` prints one register per line with its address, name, raw bytes and
+/// decoded fields.
+#[derive(Debug, Clone)]
+pub struct RegisterDump {
+    values: heapless::Vec<RegisterValue, { REGISTERS.len() }>,
+}
+
+impl RegisterDump {
+    pub fn values(&self) -> &[RegisterValue] {
+        &self.values
+    }
+
+    /// The captured value of the register named `name`, if it was read.
+    pub fn get(&self, name: &str) -> Option<&RegisterValue> {
+        self.values.iter().find(|value| value.info.name == name)
+    }
+}
+
+impl fmt::Display for RegisterDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for value in &self.values {
+            writeln!(f, "{value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RegisterDump {
+    fn format(&self, f: defmt::Formatter) {
+        for value in &self.values {
+            defmt::write!(f, "{}\n", value);
+        }
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Read every register of user banks 0, 1, 2 and 4.
+    ///
+    /// Write-only registers and registers whose read clears status flags or consumes FIFO data
+    /// are skipped, so taking a dump does not disturb the device.
+    pub async fn dump_registers(&mut self) -> Result<RegisterDump, Error<I::Error>> {
+        self.dump_registers_filtered(|reg| {
+            matches!(
+                reg.access,
+                RegisterAccess::ReadWrite | RegisterAccess::ReadOnly
+            )
+        })
+        .await
+    }
+
+    /// Read every readable register for which `filter` returns true.
+    pub async fn dump_registers_filtered(
+        &mut self,
+        mut filter: impl FnMut(&RegisterInfo) -> bool,
+    ) -> Result<RegisterDump, Error<I::Error>> {
+        let mut dump = RegisterDump {
+            values: heapless::Vec::new(),
+        };
+        for info in REGISTERS {
+            if !info.access.is_readable() || !filter(info) {
+                continue;
+            }
+            let mut bytes = [0; MAX_SIZE_BYTES];
+            self.interface()
+                .read_register(
+                    info.address,
+                    (info.size_bytes * 8) as u32,
+                    &mut bytes[..info.size_bytes],
+                )
+                .await?;
+            // Cannot fail, there is room for every register.
+            let _ = dump.values.push(RegisterValue::new(info, bytes));
+        }
+        Ok(dump)
+    }
+}
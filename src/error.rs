@@ -0,0 +1,75 @@
+//! Driver error type.
+
+use core::fmt;
+
+/// Error returned by the high-level driver APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The register interface failed.
+    Bus(E),
+    /// The device does not behave like an ICM-42605, e.g. `WHO_AM_I` is wrong or a configuration
+    /// did not read back as written.
+    InvalidDevice,
+    /// The requested configuration is reserved or not supported in the current mode.
+    InvalidConfig,
+    /// The device did not signal completion in time.
+    Timeout,
+    /// The FIFO overflowed and packets were lost.
+    FifoOverflow,
+    /// The sensor has not finished starting up or no new data is available.
+    NotReady,
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(err: E) -> Self {
+        Self::Bus(err)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bus(err) => write!(f, "bus error: {err:?}"),
+            Self::InvalidDevice => f.write_str("device is not an ICM-42605"),
+            Self::InvalidConfig => f.write_str("invalid configuration"),
+            Self::Timeout => f.write_str("timed out waiting for the device"),
+            Self::FifoOverflow => f.write_str("FIFO overflow"),
+            Self::NotReady => f.write_str("device not ready"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
+/// Error returned by [`Icm42605Interface`] and [`Icm42605I3cInterface`].
+///
+/// [`Icm42605Interface`]: crate::Icm42605Interface
+/// [`Icm42605I3cInterface`]: crate::Icm42605I3cInterface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterfaceError<E> {
+    /// The bus transfer failed.
+    Bus(E),
+    /// With bank verification enabled, `REG_BANK_SEL` did not read back the selected bank.
+    BankMismatch { expected: u8, actual: u8 },
+}
+
+impl<E> From<E> for InterfaceError<E> {
+    fn from(err: E) -> Self {
+        Self::Bus(err)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for InterfaceError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bus(err) => write!(f, "bus error: {err:?}"),
+            Self::BankMismatch { expected, actual } => {
+                write!(f, "selected bank {expected} but bank {actual} is active")
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for InterfaceError<E> {}
@@ -0,0 +1,745 @@
+//! FIFO packet format and draining.
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::registers::FIFO_DATA;
+use crate::{AxisMask, Endian, Error, FifoCountRec, FifoMode, Icm42605, MountingOrientation};
+
+/// Time the FIFO needs to complete a flush before it may be accessed again.
+const FLUSH_US: u32 = 2;
+
+/// Contents of the timestamp field of a FIFO packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoTimestampKind {
+    None,
+    /// Timestamp of the ODR sample, absolute or delta depending on `TMST_DELTA_EN`.
+    Odr,
+    /// Time from the last FSYNC edge to the ODR sample.
+    Fsync,
+}
+
+/// The header byte that starts every FIFO packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoHeader(pub u8);
+
+impl FifoHeader {
+    /// The FIFO was empty when this byte was read.
+    pub const fn is_empty(self) -> bool {
+        self.0 & 0x80 != 0
+    }
+
+    pub const fn has_accel(self) -> bool {
+        self.0 & 0x40 != 0
+    }
+
+    pub const fn has_gyro(self) -> bool {
+        self.0 & 0x20 != 0
+    }
+
+    pub const fn timestamp(self) -> FifoTimestampKind {
+        match (self.0 >> 2) & 0x03 {
+            0b10 => FifoTimestampKind::Odr,
+            0b11 => FifoTimestampKind::Fsync,
+            _ => FifoTimestampKind::None,
+        }
+    }
+
+    /// The accelerometer ODR changed with this packet.
+    pub const fn accel_odr_changed(self) -> bool {
+        self.0 & 0x02 != 0
+    }
+
+    /// The gyroscope ODR changed with this packet.
+    pub const fn gyro_odr_changed(self) -> bool {
+        self.0 & 0x01 != 0
+    }
+
+    /// Length of the packet including this header, or `None` if the header does not start a
+    /// packet.
+    pub const fn packet_len(self) -> Option<usize> {
+        if self.is_empty() {
+            return None;
+        }
+        match (self.has_accel(), self.has_gyro()) {
+            (true, true) => Some(16),
+            (true, false) | (false, true) => Some(8),
+            (false, false) => None,
+        }
+    }
+}
+
+/// A decoded FIFO packet with raw sensor values.
+///
+/// Axes of a sensor that is not part of the packet, or whose value is the invalid-data marker
+/// -32768, are `None`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoPacket {
+    pub header: FifoHeader,
+    pub accel: [Option<i16>; 3],
+    pub gyro: [Option<i16>; 3],
+    pub temperature: i8,
+    /// Raw timestamp field, interpreted according to [`FifoHeader::timestamp`].
+    pub timestamp: Option<u16>,
+}
+
+impl FifoPacket {
+    /// Decode a packet from `bytes`, which must hold exactly [`FifoHeader::packet_len`] bytes.
+    ///
+    /// Multi-byte values are decoded according to `SENSOR_DATA_ENDIAN`.
+    pub fn parse(bytes: &[u8], endian: Endian) -> Option<Self> {
+        let (&header, mut rest) = bytes.split_first()?;
+        let header = FifoHeader(header);
+        if header.packet_len()? != bytes.len() {
+            return None;
+        }
+
+        let mut take = |len: usize| {
+            let (head, tail) = rest.split_at(len);
+            rest = tail;
+            head
+        };
+        let word = |b: &[u8]| match endian {
+            Endian::BigEndian => u16::from_be_bytes([b[0], b[1]]),
+            Endian::LittleEndian => u16::from_le_bytes([b[0], b[1]]),
+        };
+        let mut axes = |present: bool| match present {
+            true => {
+                let b = take(6);
+                [0, 2, 4].map(|i| Some(word(&b[i..]) as i16).filter(|&v| v != i16::MIN))
+            }
+            false => [None; 3],
+        };
+
+        let accel = axes(header.has_accel());
+        let gyro = axes(header.has_gyro());
+        let temperature = take(1)[0] as i8;
+        let timestamp = (header.has_accel() && header.has_gyro()).then(|| word(take(2)));
+
+        Some(Self {
+            header,
+            accel,
+            gyro,
+            temperature,
+            timestamp,
+        })
+    }
+
+    /// Report the axes disabled with [`Icm42605::enable_axes`] as `None`.
+    ///
+    /// [`Icm42605::enable_axes`]: crate::Icm42605::enable_axes
+    pub fn with_axes(self, accel: AxisMask, gyro: AxisMask) -> Self {
+        Self {
+            accel: accel.mask(self.accel),
+            gyro: gyro.mask(self.gyro),
+            ..self
+        }
+    }
+
+    /// Temperature in degrees Celsius.
+    pub fn temperature_celsius(&self) -> f32 {
+        f32::from(self.temperature) / 2.07 + 25.0
+    }
+}
+
+/// An item produced by [`FifoManager::drain`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoItem {
+    Packet(FifoPacket),
+    /// Packets were lost to an overflow or discarded to resynchronize.
+    ///
+    /// In stream mode the oldest packets are dropped, so the gap precedes the packets of the same
+    /// drain; in stop-on-full mode new packets are dropped and the gap follows them.
+    Gap {
+        packets: u32,
+    },
+}
+
+/// Drains the FIFO while keeping track of lost data.
+///
+/// With `FIFO_RESUME_PARTIAL_RD` set the FIFO is read in chunks of any size, and the bytes of a
+/// packet split across reads are kept until the rest arrives. Otherwise only whole packets are
+/// read.
+///
+/// Packets are reported in the body frame set with [`FifoManager::set_mounting_orientation`].
+///
+/// The manager assumes the FIFO configuration (`FIFO_CONFIG1`, `SENSOR_CONFIG0` and the
+/// endianness in `INTF_CONFIG0`) does not change behind its back; call [`FifoManager::sync`]
+/// after changing it.
+#[derive(Debug)]
+pub struct FifoManager<D> {
+    delay: D,
+    mode: FifoMode,
+    endian: Endian,
+    count_endian: Endian,
+    packet_len: usize,
+    resume_partial: bool,
+    partial: heapless::Vec<u8, 16>,
+    /// Packet read together with a gap by [`FifoManager::read_packet`], returned next.
+    pending: Option<FifoPacket>,
+    accel_axes: AxisMask,
+    gyro_axes: AxisMask,
+    orientation: MountingOrientation,
+    lost: u16,
+    total_lost: u32,
+}
+
+impl<D: DelayNs> FifoManager<D> {
+    /// Create a manager for the FIFO as currently configured.
+    ///
+    /// `FIFO_COUNT` is switched to count bytes.
+    pub async fn new<I>(delay: D, device: &mut Icm42605<I>) -> Result<Self, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let mut manager = Self {
+            delay,
+            mode: FifoMode::Bypass,
+            endian: Endian::BigEndian,
+            count_endian: Endian::BigEndian,
+            packet_len: 0,
+            resume_partial: false,
+            partial: heapless::Vec::new(),
+            pending: None,
+            accel_axes: AxisMask::ALL,
+            gyro_axes: AxisMask::ALL,
+            orientation: MountingOrientation::IDENTITY,
+            lost: 0,
+            total_lost: 0,
+        };
+        manager.sync(device).await?;
+        Ok(manager)
+    }
+
+    /// Re-read the FIFO configuration from the device.
+    pub async fn sync<I>(&mut self, device: &mut Icm42605<I>) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let intf = device.user_bank_0().intf_config_0().read_async().await?;
+        if intf.fifo_count_rec() != FifoCountRec::Bytes {
+            device
+                .user_bank_0()
+                .intf_config_0()
+                .modify_async(|r| r.set_fifo_count_rec(FifoCountRec::Bytes))
+                .await?;
+        }
+        self.endian = intf.sensor_data_endian();
+        self.count_endian = intf.fifo_count_endian();
+
+        let config = device.user_bank_0().fifo_config_1().read_async().await?;
+        let header = FifoHeader(
+            u8::from(config.fifo_accel_en()) << 6 | u8::from(config.fifo_gyro_en()) << 5,
+        );
+        self.packet_len = header.packet_len().unwrap_or(0);
+        self.resume_partial = config.fifo_resume_partial_rd();
+        self.partial.clear();
+        self.pending = None;
+        self.mode = device
+            .user_bank_0()
+            .fifo_config()
+            .read_async()
+            .await?
+            .fifo_mode();
+        (self.accel_axes, self.gyro_axes) = device.enabled_axes().await?;
+        self.lost = device
+            .user_bank_0()
+            .fifo_lost_pkt()
+            .read_async()
+            .await?
+            .value();
+        Ok(())
+    }
+
+    pub fn mounting_orientation(&self) -> MountingOrientation {
+        self.orientation
+    }
+
+    /// Set how the sensor is mounted, so that packets are reported in its body frame.
+    pub fn set_mounting_orientation(&mut self, orientation: MountingOrientation) {
+        self.orientation = orientation;
+    }
+
+    pub fn mode(&self) -> FifoMode {
+        self.mode
+    }
+
+    /// Total number of packets lost or discarded since the manager was created.
+    pub fn lost_packets(&self) -> u32 {
+        self.total_lost
+    }
+
+    /// Whether the FIFO may be read in chunks that split packets.
+    pub fn partial_reads(&self) -> bool {
+        self.resume_partial
+    }
+
+    /// Allow or disallow reads that split packets (`FIFO_RESUME_PARTIAL_RD`) and flush the FIFO.
+    pub async fn set_partial_reads<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        enable: bool,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        device
+            .user_bank_0()
+            .fifo_config_1()
+            .modify_async(|r| r.set_fifo_resume_partial_rd(enable))
+            .await?;
+        self.resume_partial = enable;
+        self.flush(device).await
+    }
+
+    /// Switch the FIFO to `mode` and flush it.
+    pub async fn set_mode<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        mode: FifoMode,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if mode == FifoMode::Reserved {
+            return Err(Error::InvalidConfig);
+        }
+        device
+            .user_bank_0()
+            .fifo_config()
+            .write_async(|r| r.set_fifo_mode(mode))
+            .await?;
+        self.mode = mode;
+        self.flush(device).await?;
+        Ok(())
+    }
+
+    /// Discard the contents of the FIFO and wait for the flush to complete.
+    pub async fn flush<I>(&mut self, device: &mut Icm42605<I>) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        device
+            .user_bank_0()
+            .signal_path_reset()
+            .write_async(|r| r.set_fifo_flush(true))
+            .await?;
+        self.delay.delay_us(FLUSH_US).await;
+        self.partial.clear();
+        self.pending = None;
+        self.lost = device
+            .user_bank_0()
+            .fifo_lost_pkt()
+            .read_async()
+            .await?
+            .value();
+        Ok(())
+    }
+
+    /// Read as much data as is available and fits into `buf`, passing the packets to `f`.
+    ///
+    /// Unless partial reads are enabled, only whole packets are read, so `buf` must hold at least
+    /// one packet for any progress to be made. Lost packets are reported as [`FifoItem::Gap`]. If
+    /// the data read does not parse as packets of the configured format, the FIFO is flushed and
+    /// the discarded packets are reported as a gap as well. Returns the number of packets read.
+    pub async fn drain<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        buf: &mut [u8],
+        mut f: impl FnMut(FifoItem),
+    ) -> Result<usize, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if self.packet_len == 0 {
+            return Ok(0);
+        }
+        let mut packets = 0;
+        if let Some(packet) = self.pending.take() {
+            f(FifoItem::Packet(packet));
+            packets += 1;
+        }
+
+        let lost = device
+            .user_bank_0()
+            .fifo_lost_pkt()
+            .read_async()
+            .await?
+            .value();
+        let gap = u32::from(lost.wrapping_sub(self.lost));
+        self.lost = lost;
+        self.total_lost += gap;
+        let stream = self.mode == FifoMode::Stream;
+        if gap > 0 && stream {
+            f(FifoItem::Gap { packets: gap });
+        }
+
+        let available = self.read_count(device).await?;
+        let mut len = available.min(buf.len());
+        if !self.resume_partial {
+            len -= len % self.packet_len;
+        }
+        if len > 0 {
+            let [bank, reg] = FIFO_DATA.to_be_bytes();
+            device.read_block(bank, reg, &mut buf[..len]).await?;
+
+            let mut data = &buf[..len];
+            // Every byte read was counted as FIFO data, so a header marking the FIFO as empty is
+            // out of step as well.
+            while !data.is_empty() {
+                let (bytes, rest) =
+                    data.split_at((self.packet_len - self.partial.len()).min(data.len()));
+                data = rest;
+                // Cannot overflow, at most `packet_len` bytes are ever held.
+                let _ = self.partial.extend_from_slice(bytes);
+                if self.partial.len() < self.packet_len {
+                    break;
+                }
+
+                let packet = FifoPacket::parse(&self.partial, self.endian);
+                self.partial.clear();
+                match packet {
+                    Some(packet) => {
+                        let packet = packet.with_axes(self.accel_axes, self.gyro_axes);
+                        f(FifoItem::Packet(self.orientation.apply_packet(&packet)));
+                        packets += 1;
+                    }
+                    None => {
+                        // Out of step with the packet boundaries; start over.
+                        let remaining = self.read_count(device).await?;
+                        self.flush(device).await?;
+                        let discarded = 1 + (data.len() + remaining).div_ceil(self.packet_len);
+                        self.total_lost += discarded as u32;
+                        f(FifoItem::Gap {
+                            packets: discarded as u32,
+                        });
+                        break;
+                    }
+                }
+            }
+        }
+
+        if gap > 0 && !stream {
+            f(FifoItem::Gap { packets: gap });
+        }
+        Ok(packets)
+    }
+
+    /// Read the next packet.
+    ///
+    /// Fails with [`Error::NotReady`] if no whole packet is available yet, and with
+    /// [`Error::FifoOverflow`] if packets were lost or discarded since the previous read. A packet
+    /// read together with the overflow is returned by the next call. Fails with
+    /// [`Error::InvalidConfig`] if neither sensor writes to the FIFO.
+    pub async fn read_packet<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+    ) -> Result<FifoPacket, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if self.packet_len == 0 {
+            return Err(Error::InvalidConfig);
+        }
+        if let Some(packet) = self.pending.take() {
+            return Ok(packet);
+        }
+
+        // With at most one packet's worth of bytes, a drain yields at most one packet.
+        let mut buf = [0; 16];
+        let mut packet = None;
+        let mut gap = false;
+        self.drain(device, &mut buf[..self.packet_len], |item| match item {
+            FifoItem::Packet(p) => packet = Some(p),
+            FifoItem::Gap { .. } => gap = true,
+        })
+        .await?;
+        match (gap, packet) {
+            (true, packet) => {
+                self.pending = packet;
+                Err(Error::FifoOverflow)
+            }
+            (false, Some(packet)) => Ok(packet),
+            (false, None) => Err(Error::NotReady),
+        }
+    }
+
+    /// Release the delay provider.
+    pub fn free(self) -> D {
+        self.delay
+    }
+
+    async fn read_count<I>(&mut self, device: &mut Icm42605<I>) -> Result<usize, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let count = device
+            .user_bank_0()
+            .fifo_count()
+            .read_async()
+            .await?
+            .value() as u16;
+        Ok(usize::from(match self.count_endian {
+            Endian::BigEndian => count,
+            Endian::LittleEndian => count.swap_bytes(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::mock::{MockError, RegisterSpace, impl_interfaces};
+    use crate::registers::FIFO_DATA;
+    use crate::sim::{Motion, MotionProfile, SimBus, SimDelay, Simulator, Stationary};
+    use crate::{AccelMode, Icm42605, MountingMatrix};
+
+    /// A simulated bus that overwrites one byte of the next FIFO read.
+    struct Tamper {
+        bus: SimBus<Stationary>,
+        /// Index in the read and the value written there.
+        byte: Option<(usize, u8)>,
+    }
+
+    impl RegisterSpace for Tamper {
+        fn i2c_address(&self) -> u8 {
+            self.bus.i2c_address()
+        }
+
+        fn bank(&self) -> u8 {
+            RegisterSpace::bank(&self.bus)
+        }
+
+        fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError> {
+            self.bus.read(bank, reg, data)?;
+            if u16::from_be_bytes([bank, reg]) == FIFO_DATA
+                && let Some((index, value)) = self.byte.take()
+            {
+                data[index] = value;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError> {
+            self.bus.write(bank, reg, data)
+        }
+    }
+
+    impl_interfaces!(Tamper);
+
+    /// Drain with a buffer of `len` bytes, collecting the items.
+    fn drain<I>(
+        fifo: &mut FifoManager<impl DelayNs>,
+        device: &mut Icm42605<I>,
+        len: usize,
+    ) -> Vec<FifoItem>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+        I::Error: core::fmt::Debug,
+    {
+        let mut buf = [0; 64];
+        let mut items = Vec::new();
+        block_on(fifo.drain(device, &mut buf[..len], |item| items.push(item))).unwrap();
+        items
+    }
+
+    /// Stream the accelerometer into the FIFO at 1 kHz.
+    fn stream<M: MotionProfile>(sim: &Simulator<M>) -> Icm42605<SimBus<M>> {
+        let mut device = Icm42605::new(sim.bus());
+        device
+            .user_bank_0()
+            .fifo_config_1()
+            .write(|r| r.set_fifo_accel_en(true))
+            .unwrap();
+        device
+            .user_bank_0()
+            .fifo_config()
+            .write(|r| r.set_fifo_mode(FifoMode::Stream))
+            .unwrap();
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| r.set_accel_mode(AccelMode::LowNoise))
+            .unwrap();
+        device
+    }
+
+    #[test]
+    fn read_packet_waits_for_whole_packets() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+
+        assert_eq!(
+            block_on(fifo.read_packet(&mut device)),
+            Err(Error::NotReady)
+        );
+        sim.advance(2_000);
+        for _ in 0..2 {
+            let packet = block_on(fifo.read_packet(&mut device)).unwrap();
+            assert!(packet.header.has_accel());
+        }
+        assert_eq!(
+            block_on(fifo.read_packet(&mut device)),
+            Err(Error::NotReady)
+        );
+    }
+
+    #[test]
+    fn read_packet_reports_overflow_once() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+
+        sim.advance(300_000);
+        assert_eq!(
+            block_on(fifo.read_packet(&mut device)),
+            Err(Error::FifoOverflow)
+        );
+        assert!(fifo.lost_packets() > 0);
+        assert!(block_on(fifo.read_packet(&mut device)).is_ok());
+        assert!(block_on(fifo.read_packet(&mut device)).is_ok());
+    }
+
+    #[test]
+    fn packets_are_reported_in_the_body_frame() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+        fifo.set_mounting_orientation(MountingMatrix::NegXYNegZ.into());
+        block_on(fifo.sync(&mut device)).unwrap();
+
+        sim.advance(1_000);
+        let packet = block_on(fifo.read_packet(&mut device)).unwrap();
+        assert!(packet.accel[2].unwrap() < -2000);
+    }
+
+    /// Stream the accelerometer through a [`Tamper`] bus and fill the FIFO with four packets.
+    fn tampered(
+        sim: &Simulator<Stationary>,
+    ) -> (Icm42605<Tamper>, FifoManager<SimDelay<Stationary>>) {
+        drop(stream(sim));
+        let mut device = Icm42605::new(Tamper {
+            bus: sim.bus(),
+            byte: None,
+        });
+        let fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+        sim.advance(4_000);
+        assert_eq!(sim.fifo_len(), 32);
+        (device, fifo)
+    }
+
+    #[test]
+    fn corrupt_headers_resync_and_report_the_discarded_packets() {
+        let sim = Simulator::new(false, Stationary);
+        let (mut device, mut fifo) = tampered(&sim);
+
+        // The first header claims no sensor data; the three packets after it are discarded too.
+        device.interface().byte = Some((0, 0x00));
+        let items = drain(&mut fifo, &mut device, 64);
+        assert!(matches!(items[..], [FifoItem::Gap { packets: 4 }]));
+        assert_eq!(fifo.lost_packets(), 4);
+        assert_eq!(sim.fifo_len(), 0);
+
+        sim.advance(2_000);
+        let items = drain(&mut fifo, &mut device, 64);
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| matches!(item, FifoItem::Packet(_))));
+    }
+
+    #[test]
+    fn empty_headers_within_the_data_resync() {
+        let sim = Simulator::new(false, Stationary);
+        let (mut device, mut fifo) = tampered(&sim);
+
+        // The second packet claims the FIFO is empty, so it and the two after it are discarded.
+        device.interface().byte = Some((8, 0x80));
+        let items = drain(&mut fifo, &mut device, 64);
+        assert!(matches!(
+            items[..],
+            [FifoItem::Packet(_), FifoItem::Gap { packets: 3 }]
+        ));
+        assert_eq!(fifo.lost_packets(), 3);
+        assert_eq!(sim.fifo_len(), 0);
+    }
+
+    #[test]
+    fn set_mode_switches_the_mode_and_flushes() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+        assert_eq!(fifo.mode(), FifoMode::Stream);
+        sim.advance(2_000);
+
+        block_on(fifo.set_mode(&mut device, FifoMode::StopOnFull)).unwrap();
+        assert_eq!(fifo.mode(), FifoMode::StopOnFull);
+        let config = device.user_bank_0().fifo_config().read().unwrap();
+        assert_eq!(config.fifo_mode(), FifoMode::StopOnFull);
+        assert_eq!(sim.fifo_len(), 0);
+
+        assert_eq!(
+            block_on(fifo.set_mode(&mut device, FifoMode::Reserved)),
+            Err(Error::InvalidConfig)
+        );
+        assert_eq!(fifo.mode(), FifoMode::StopOnFull);
+    }
+
+    #[test]
+    fn flush_discards_data_and_losses_without_a_gap() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+
+        sim.advance(300_000);
+        block_on(fifo.flush(&mut device)).unwrap();
+        assert_eq!(sim.fifo_len(), 0);
+        assert!(drain(&mut fifo, &mut device, 64).is_empty());
+
+        sim.advance(1_000);
+        let items = drain(&mut fifo, &mut device, 64);
+        assert!(matches!(items[..], [FifoItem::Packet(_)]));
+        assert_eq!(fifo.lost_packets(), 0);
+    }
+
+    /// Accelerometer X steps by 1/64 g, 32 LSB at ±16 g, every millisecond.
+    fn ramp(time_us: u64) -> Motion {
+        Motion {
+            accel: [(time_us / 1_000) as f32 / 64.0, 0.0, 1.0],
+            ..Motion::default()
+        }
+    }
+
+    #[test]
+    fn partial_reads_carry_packets_across_small_buffers() {
+        let sim = Simulator::new(false, ramp as fn(u64) -> Motion);
+        let mut device = stream(&sim);
+        let mut fifo = block_on(FifoManager::new(sim.delay(), &mut device)).unwrap();
+        block_on(fifo.set_partial_reads(&mut device, true)).unwrap();
+
+        let mut xs = Vec::new();
+        for advance in [5_000, 3_000] {
+            sim.advance(advance);
+            let bytes = sim.fifo_len();
+            let before = xs.len();
+            while sim.fifo_len() > 0 {
+                for item in drain(&mut fifo, &mut device, 5) {
+                    match item {
+                        FifoItem::Packet(packet) => xs.push(packet.accel[0].unwrap()),
+                        FifoItem::Gap { .. } => panic!("unexpected gap"),
+                    }
+                }
+            }
+            assert_eq!(xs.len() - before, bytes / 8);
+        }
+        assert_eq!(xs.len(), 8);
+        assert!(xs.windows(2).all(|pair| pair[1] - pair[0] == 32));
+    }
+}
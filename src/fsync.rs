@@ -0,0 +1,263 @@
+//! External frame synchronization through the FSYNC input on pin 9.
+
+use core::convert::Infallible;
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::digital::Wait;
+
+use crate::{
+    Error, FifoPacket, FifoTimestampKind, FsyncFlag, FsyncPolarity, Icm42605, InterruptPin,
+    Pin9Function, TimestampResolution,
+};
+
+/// An FSYNC edge, with the delay from the edge to the following ODR sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FsyncEvent {
+    pub delay_micros: u32,
+}
+
+impl FsyncEvent {
+    /// Decode the FSYNC delay carried in place of the timestamp by a FIFO packet.
+    pub fn from_fifo(packet: &FifoPacket, resolution: TimestampResolution) -> Option<Self> {
+        if packet.header.timestamp() != FifoTimestampKind::Fsync {
+            return None;
+        }
+        Some(Self::from_ticks(packet.timestamp?, resolution))
+    }
+
+    fn from_ticks(ticks: u16, resolution: TimestampResolution) -> Self {
+        Self {
+            delay_micros: u32::from(ticks) * resolution.micros(),
+        }
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Switch pin 9 to FSYNC input and tag the LSB of the sensor selected by `tag` with the
+    /// FSYNC state.
+    ///
+    /// The FSYNC delay is also recorded in `TIMESTAMP_FSYNC` and in place of the timestamp of
+    /// FIFO packets, and the FSYNC interrupt is routed to `interrupt` if given. Pin 9 is the INT2
+    /// output otherwise, so routing the interrupt to [`InterruptPin::Int2`] fails with
+    /// [`Error::InvalidConfig`].
+    pub async fn enable_fsync(
+        &mut self,
+        tag: FsyncFlag,
+        polarity: FsyncPolarity,
+        interrupt: Option<InterruptPin>,
+    ) -> Result<(), Error<I::Error>> {
+        if interrupt == Some(InterruptPin::Int2) {
+            return Err(Error::InvalidConfig);
+        }
+        self.user_bank_1()
+            .intf_config_5()
+            .modify_async(|r| r.set_pin_9_function(Pin9Function::FSync))
+            .await?;
+        self.user_bank_0()
+            .fsync_config()
+            .modify_async(|r| {
+                r.set_fsync_ui_sel(tag);
+                r.set_fsync_polarity(polarity);
+            })
+            .await?;
+        self.user_bank_0()
+            .tmst_config()
+            .modify_async(|r| r.set_tmst_fsync_en(true))
+            .await?;
+        self.user_bank_0()
+            .fifo_config_1()
+            .modify_async(|r| r.set_fifo_tmst_fsync_en(true))
+            .await?;
+
+        self.user_bank_0()
+            .int_source_0()
+            .modify_async(|r| r.set_ui_fsync_int_1_en(interrupt.is_some()))
+            .await?;
+        self.user_bank_0()
+            .int_source_3()
+            .modify_async(|r| r.set_ui_fsync_int_2_en(false))
+            .await?;
+        Ok(())
+    }
+
+    /// Stop tagging samples and timestamps with FSYNC and return pin 9 to its INT2 function.
+    pub async fn disable_fsync(&mut self) -> Result<(), Error<I::Error>> {
+        self.user_bank_0()
+            .fsync_config()
+            .modify_async(|r| r.set_fsync_ui_sel(FsyncFlag::Disabled))
+            .await?;
+        self.user_bank_0()
+            .tmst_config()
+            .modify_async(|r| r.set_tmst_fsync_en(false))
+            .await?;
+        self.user_bank_0()
+            .fifo_config_1()
+            .modify_async(|r| r.set_fifo_tmst_fsync_en(false))
+            .await?;
+        self.user_bank_0()
+            .int_source_0()
+            .modify_async(|r| r.set_ui_fsync_int_1_en(false))
+            .await?;
+        self.user_bank_0()
+            .int_source_3()
+            .modify_async(|r| r.set_ui_fsync_int_2_en(false))
+            .await?;
+        self.user_bank_1()
+            .intf_config_5()
+            .modify_async(|r| r.set_pin_9_function(Pin9Function::Int2))
+            .await?;
+        Ok(())
+    }
+
+    /// Read and clear `INT_STATUS`, returning the FSYNC event it reports, if any.
+    ///
+    /// Reading the status clears the data ready and FIFO interrupt flags as well.
+    pub async fn read_fsync_event(&mut self) -> Result<Option<FsyncEvent>, Error<I::Error>> {
+        let status = self.user_bank_0().int_status().read_async().await?;
+        if !status.ui_fsync_int() {
+            return Ok(None);
+        }
+
+        let resolution = if self
+            .user_bank_0()
+            .tmst_config()
+            .read_async()
+            .await?
+            .tmst_res()
+        {
+            TimestampResolution::Micros16
+        } else {
+            TimestampResolution::Micros1
+        };
+        let ticks = self
+            .user_bank_0()
+            .timestamp_fsync()
+            .read_async()
+            .await?
+            .value() as u16;
+        Ok(Some(FsyncEvent::from_ticks(ticks, resolution)))
+    }
+
+    /// Wait on the interrupt pin passed to [`Self::enable_fsync`] for the next FSYNC event.
+    pub async fn wait_for_fsync_event<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<FsyncEvent, Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        loop {
+            self.wait_for_interrupt(int, pin).await?;
+            if let Some(event) = self.read_fsync_event().await? {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::registers::{INT_STATUS, TIMESTAMP_FSYNC};
+
+    fn pin_9_function(device: &mut Icm42605<MockDevice>) -> Pin9Function {
+        let config = device.user_bank_1().intf_config_5().read().unwrap();
+        config.pin_9_function()
+    }
+
+    #[test]
+    fn fsync_takes_over_pin_9() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        assert_eq!(pin_9_function(&mut device), Pin9Function::Int2);
+
+        block_on(device.enable_fsync(
+            FsyncFlag::AccelZ,
+            FsyncPolarity::FallingEdge,
+            Some(InterruptPin::Int1),
+        ))
+        .unwrap();
+        assert_eq!(pin_9_function(&mut device), Pin9Function::FSync);
+        let config = device.user_bank_0().fsync_config().read().unwrap();
+        assert_eq!(config.fsync_ui_sel(), FsyncFlag::AccelZ);
+        assert_eq!(config.fsync_polarity(), FsyncPolarity::FallingEdge);
+        assert!(
+            device
+                .user_bank_0()
+                .tmst_config()
+                .read()
+                .unwrap()
+                .tmst_fsync_en()
+        );
+        assert!(
+            device
+                .user_bank_0()
+                .fifo_config_1()
+                .read()
+                .unwrap()
+                .fifo_tmst_fsync_en()
+        );
+        assert!(
+            device
+                .user_bank_0()
+                .int_source_0()
+                .read()
+                .unwrap()
+                .ui_fsync_int_1_en()
+        );
+
+        block_on(device.disable_fsync()).unwrap();
+        assert_eq!(pin_9_function(&mut device), Pin9Function::Int2);
+        let config = device.user_bank_0().fsync_config().read().unwrap();
+        assert_eq!(config.fsync_ui_sel(), FsyncFlag::Disabled);
+        assert!(
+            !device
+                .user_bank_0()
+                .tmst_config()
+                .read()
+                .unwrap()
+                .tmst_fsync_en()
+        );
+        assert!(
+            !device
+                .user_bank_0()
+                .int_source_0()
+                .read()
+                .unwrap()
+                .ui_fsync_int_1_en()
+        );
+    }
+
+    #[test]
+    fn fsync_interrupt_cannot_use_pin_9() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+
+        assert_eq!(
+            block_on(device.enable_fsync(
+                FsyncFlag::GyroX,
+                FsyncPolarity::RisingEdge,
+                Some(InterruptPin::Int2),
+            )),
+            Err(Error::InvalidConfig)
+        );
+        assert!(device.interface().transactions().is_empty());
+    }
+
+    #[test]
+    fn fsync_events_carry_the_delay_to_the_next_sample() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        assert_eq!(block_on(device.read_fsync_event()), Ok(None));
+
+        device.interface().poke(TIMESTAMP_FSYNC, &[0x01, 0x2c]);
+        device.interface().poke(INT_STATUS, &[1 << 6]);
+        assert_eq!(
+            block_on(device.read_fsync_event()),
+            Ok(Some(FsyncEvent { delay_micros: 300 }))
+        );
+        assert_eq!(block_on(device.read_fsync_event()), Ok(None));
+    }
+}
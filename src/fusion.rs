@@ -0,0 +1,466 @@
+//! Orientation estimation from accelerometer and gyroscope samples.
+//!
+//! [`Madgwick`] and [`Mahony`] track the orientation of the sensor frame relative to an
+//! earth frame with Z pointing up, as a unit [`Quaternion`]. Both are initialized from the first
+//! accelerometer reading, so roll and pitch start out correct and yaw starts at zero.
+
+use libm::{asinf, atan2f, cosf, sinf, sqrtf};
+
+use crate::{DataRate, ScaledSample};
+
+/// A rotation as a unit quaternion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Self = Self {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// The orientation with zero yaw in which gravity is measured as `accel`, or `None` if
+    /// `accel` is zero.
+    pub fn from_accel(accel: [f32; 3]) -> Option<Self> {
+        let [ax, ay, az] = normalize(accel)?;
+        let roll = atan2f(ay, az);
+        let pitch = atan2f(-ax, sqrtf(ay * ay + az * az));
+        let (sr, cr) = (sinf(roll / 2.0), cosf(roll / 2.0));
+        let (sp, cp) = (sinf(pitch / 2.0), cosf(pitch / 2.0));
+        Some(Self {
+            w: cr * cp,
+            x: sr * cp,
+            y: cr * sp,
+            z: -sr * sp,
+        })
+    }
+
+    /// Roll, pitch and yaw in radians.
+    pub fn euler_angles(&self) -> [f32; 3] {
+        let Self { w, x, y, z } = *self;
+        let roll = atan2f(2.0 * (w * x + y * z), 1.0 - 2.0 * (x * x + y * y));
+        let pitch = asinf((2.0 * (w * y - z * x)).clamp(-1.0, 1.0));
+        let yaw = atan2f(2.0 * (w * z + x * y), 1.0 - 2.0 * (y * y + z * z));
+        [roll, pitch, yaw]
+    }
+
+    fn normalized(self) -> Self {
+        let norm = sqrtf(self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z);
+        if norm == 0.0 {
+            return Self::IDENTITY;
+        }
+        Self {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+}
+
+fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2]);
+    (norm > 0.0).then(|| v.map(|c| c / norm))
+}
+
+/// Timing and initialization shared by the filters.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+struct Timeline {
+    last_us: Option<u64>,
+    /// Time of samples taken at a fixed rate.
+    clock_us: u64,
+}
+
+impl Timeline {
+    /// Account for `sample` at `timestamp_us`, returning the accelerometer reading, the angular
+    /// rate in rad/s and the time step in seconds if the filter should be updated.
+    ///
+    /// The first sample initializes `q` from the accelerometer instead.
+    fn step(
+        &mut self,
+        q: &mut Quaternion,
+        sample: &ScaledSample,
+        timestamp_us: u64,
+    ) -> Option<([f32; 3], [f32; 3], f32)> {
+        let (accel, gyro) = (sample.accel_vector()?, sample.gyro_vector()?);
+        let Some(last_us) = self.last_us.replace(timestamp_us) else {
+            *q = Quaternion::from_accel(accel).unwrap_or(Quaternion::IDENTITY);
+            return None;
+        };
+        let dt = timestamp_us.checked_sub(last_us).filter(|&dt| dt > 0)? as f32 * 1e-6;
+        Some((accel, gyro.map(f32::to_radians), dt))
+    }
+
+    /// Advance the fixed-rate clock by one period of `odr`, returning the time of the sample.
+    fn tick(&mut self, odr: DataRate) -> Option<u64> {
+        self.clock_us += odr.period()?.as_micros() as u64;
+        Some(self.clock_us)
+    }
+}
+
+/// Madgwick's gradient descent orientation filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Madgwick {
+    q: Quaternion,
+    beta: f32,
+    timeline: Timeline,
+}
+
+impl Default for Madgwick {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_BETA)
+    }
+}
+
+impl Madgwick {
+    pub const DEFAULT_BETA: f32 = 0.1;
+
+    /// Create a filter with gain `beta`, the weight of the accelerometer correction.
+    pub const fn new(beta: f32) -> Self {
+        Self {
+            q: Quaternion::IDENTITY,
+            beta,
+            timeline: Timeline {
+                last_us: None,
+                clock_us: 0,
+            },
+        }
+    }
+
+    pub const fn beta(&self) -> f32 {
+        self.beta
+    }
+
+    pub fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+    }
+
+    pub const fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    /// Forget the orientation, so that the next sample initializes it again.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.beta);
+    }
+
+    /// Update the filter with a sample taken at `timestamp_us` microseconds.
+    ///
+    /// Samples without valid data on all accelerometer and gyroscope axes are ignored.
+    pub fn update(&mut self, sample: &ScaledSample, timestamp_us: u64) -> Quaternion {
+        if let Some((accel, gyro, dt)) = self.timeline.step(&mut self.q, sample, timestamp_us) {
+            self.update_imu(accel, gyro, dt);
+        }
+        self.q
+    }
+
+    /// Update the filter with the next of a series of samples taken at `odr`, such as the
+    /// packets drained from the FIFO.
+    ///
+    /// Samples at reserved rates are ignored.
+    pub fn update_at_rate(&mut self, sample: &ScaledSample, odr: DataRate) -> Quaternion {
+        match self.timeline.tick(odr) {
+            Some(timestamp_us) => self.update(sample, timestamp_us),
+            None => self.q,
+        }
+    }
+
+    /// Update the filter with acceleration in any unit, angular rate in rad/s and the time step
+    /// in seconds.
+    pub fn update_imu(&mut self, accel: [f32; 3], gyro: [f32; 3], dt: f32) {
+        let Quaternion {
+            w: q0,
+            x: q1,
+            y: q2,
+            z: q3,
+        } = self.q;
+        let [gx, gy, gz] = gyro;
+
+        // Rate of change of the quaternion from the gyroscope.
+        let mut dq = [
+            0.5 * (-q1 * gx - q2 * gy - q3 * gz),
+            0.5 * (q0 * gx + q2 * gz - q3 * gy),
+            0.5 * (q0 * gy - q1 * gz + q3 * gx),
+            0.5 * (q0 * gz + q1 * gy - q2 * gx),
+        ];
+
+        if let Some([ax, ay, az]) = normalize(accel) {
+            // Gradient of the error between measured and estimated gravity.
+            let (q0q0, q1q1, q2q2, q3q3) = (q0 * q0, q1 * q1, q2 * q2, q3 * q3);
+            let step = [
+                4.0 * q0 * q2q2 + 2.0 * q2 * ax + 4.0 * q0 * q1q1 - 2.0 * q1 * ay,
+                4.0 * q1 * q3q3 - 2.0 * q3 * ax + 4.0 * q0q0 * q1 - 2.0 * q0 * ay - 4.0 * q1
+                    + 8.0 * q1 * q1q1
+                    + 8.0 * q1 * q2q2
+                    + 4.0 * q1 * az,
+                4.0 * q0q0 * q2 + 2.0 * q0 * ax + 4.0 * q2 * q3q3 - 2.0 * q3 * ay - 4.0 * q2
+                    + 8.0 * q2 * q1q1
+                    + 8.0 * q2 * q2q2
+                    + 4.0 * q2 * az,
+                4.0 * q1q1 * q3 - 2.0 * q1 * ax + 4.0 * q2q2 * q3 - 2.0 * q2 * ay,
+            ];
+            let norm = sqrtf(step.iter().map(|s| s * s).sum());
+            if norm > 0.0 {
+                for (dq, s) in dq.iter_mut().zip(step) {
+                    *dq -= self.beta * s / norm;
+                }
+            }
+        }
+
+        self.q = Quaternion {
+            w: q0 + dq[0] * dt,
+            x: q1 + dq[1] * dt,
+            y: q2 + dq[2] * dt,
+            z: q3 + dq[3] * dt,
+        }
+        .normalized();
+    }
+}
+
+/// Mahony's complementary orientation filter with proportional and integral feedback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Mahony {
+    q: Quaternion,
+    kp: f32,
+    ki: f32,
+    integral: [f32; 3],
+    timeline: Timeline,
+}
+
+impl Default for Mahony {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_KP, Self::DEFAULT_KI)
+    }
+}
+
+impl Mahony {
+    pub const DEFAULT_KP: f32 = 1.0;
+    pub const DEFAULT_KI: f32 = 0.0;
+
+    /// Create a filter with proportional gain `kp` and integral gain `ki`, which corrects
+    /// gyroscope bias.
+    pub const fn new(kp: f32, ki: f32) -> Self {
+        Self {
+            q: Quaternion::IDENTITY,
+            kp,
+            ki,
+            integral: [0.0; 3],
+            timeline: Timeline {
+                last_us: None,
+                clock_us: 0,
+            },
+        }
+    }
+
+    /// Proportional and integral gain.
+    pub const fn gains(&self) -> (f32, f32) {
+        (self.kp, self.ki)
+    }
+
+    pub fn set_gains(&mut self, kp: f32, ki: f32) {
+        self.kp = kp;
+        self.ki = ki;
+    }
+
+    pub const fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    /// The estimated gyroscope bias in rad/s, negated.
+    pub const fn integral(&self) -> [f32; 3] {
+        self.integral
+    }
+
+    /// Forget the orientation and bias estimate, so that the next sample initializes them again.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.kp, self.ki);
+    }
+
+    /// Update the filter with a sample taken at `timestamp_us` microseconds.
+    ///
+    /// Samples without valid data on all accelerometer and gyroscope axes are ignored.
+    pub fn update(&mut self, sample: &ScaledSample, timestamp_us: u64) -> Quaternion {
+        if let Some((accel, gyro, dt)) = self.timeline.step(&mut self.q, sample, timestamp_us) {
+            self.update_imu(accel, gyro, dt);
+        }
+        self.q
+    }
+
+    /// Update the filter with the next of a series of samples taken at `odr`, such as the
+    /// packets drained from the FIFO.
+    ///
+    /// Samples at reserved rates are ignored.
+    pub fn update_at_rate(&mut self, sample: &ScaledSample, odr: DataRate) -> Quaternion {
+        match self.timeline.tick(odr) {
+            Some(timestamp_us) => self.update(sample, timestamp_us),
+            None => self.q,
+        }
+    }
+
+    /// Update the filter with acceleration in any unit, angular rate in rad/s and the time step
+    /// in seconds.
+    pub fn update_imu(&mut self, accel: [f32; 3], gyro: [f32; 3], dt: f32) {
+        let Quaternion {
+            w: q0,
+            x: q1,
+            y: q2,
+            z: q3,
+        } = self.q;
+        let mut gyro = gyro;
+
+        if let Some([ax, ay, az]) = normalize(accel) {
+            // Half the estimated direction of gravity.
+            let vx = q1 * q3 - q0 * q2;
+            let vy = q0 * q1 + q2 * q3;
+            let vz = q0 * q0 - 0.5 + q3 * q3;
+            // Half the error between measured and estimated gravity.
+            let error = [ay * vz - az * vy, az * vx - ax * vz, ax * vy - ay * vx];
+
+            for ((g, i), e) in gyro.iter_mut().zip(&mut self.integral).zip(error) {
+                if self.ki > 0.0 {
+                    *i += 2.0 * self.ki * e * dt;
+                    *g += *i;
+                } else {
+                    *i = 0.0;
+                }
+                *g += 2.0 * self.kp * e;
+            }
+        }
+
+        let [gx, gy, gz] = gyro.map(|g| g * 0.5 * dt);
+        self.q = Quaternion {
+            w: q0 - q1 * gx - q2 * gy - q3 * gz,
+            x: q1 + q0 * gx + q2 * gz - q3 * gy,
+            y: q2 + q0 * gy - q1 * gz + q3 * gx,
+            z: q3 + q0 * gz + q1 * gy - q2 * gx,
+        }
+        .normalized();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.into_iter().zip(expected) {
+            assert!((a - e).abs() < EPSILON, "{actual:?} != {expected:?}");
+        }
+    }
+
+    /// Direction of gravity in the sensor frame for the orientation `q`.
+    fn gravity(q: Quaternion) -> [f32; 3] {
+        let Quaternion { w, x, y, z } = q;
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (w * x + y * z),
+            w * w - x * x - y * y + z * z,
+        ]
+    }
+
+    fn sample(accel: [f32; 3], gyro_dps: [f32; 3]) -> ScaledSample {
+        ScaledSample {
+            accel: accel.map(Some),
+            gyro: gyro_dps.map(Some),
+            temperature: 25.0,
+        }
+    }
+
+    #[test]
+    fn from_accel_levels_gravity_on_each_axis() {
+        let cases = [
+            ([0.0, 0.0, 1.0], [0.0, 0.0]),
+            ([0.0, 0.0, -1.0], [PI, 0.0]),
+            ([0.0, 1.0, 0.0], [FRAC_PI_2, 0.0]),
+            ([0.0, -1.0, 0.0], [-FRAC_PI_2, 0.0]),
+            ([1.0, 0.0, 0.0], [0.0, -FRAC_PI_2]),
+            ([-1.0, 0.0, 0.0], [0.0, FRAC_PI_2]),
+        ];
+        for (accel, [roll, pitch]) in cases {
+            let q = Quaternion::from_accel(accel).unwrap();
+            assert_close(gravity(q), accel);
+            let [r, p, _] = q.euler_angles();
+            // Roll is undefined at ±90° pitch, and ±180° are the same angle.
+            if pitch == 0.0 {
+                assert!(sinf(r - roll).abs() < EPSILON && cosf(r - roll) > 0.0);
+            }
+            assert!((p - pitch).abs() < EPSILON);
+        }
+
+        let q = Quaternion::from_accel([0.5, -0.3, 0.8]).unwrap();
+        assert_close(gravity(q), normalize([0.5, -0.3, 0.8]).unwrap());
+        assert!(q.euler_angles()[2].abs() < EPSILON);
+        assert_eq!(Quaternion::from_accel([0.0; 3]), None);
+    }
+
+    /// Within 0.01 rad, as Madgwick's fixed step size makes it circle the level orientation.
+    fn assert_level(q: Quaternion) {
+        let [roll, pitch, _] = q.euler_angles();
+        assert!(roll.abs() < 0.01 && pitch.abs() < 0.01, "{roll} {pitch}");
+    }
+
+    /// Feed a level, stationary sample at 100 Hz for 20 s, starting tilted.
+    fn settle(mut update: impl FnMut([f32; 3], [f32; 3], f32)) {
+        for _ in 0..2000 {
+            update([0.0, 0.0, 1.0], [0.0; 3], 0.01);
+        }
+    }
+
+    fn tilted() -> Quaternion {
+        Quaternion::from_accel([0.5, -0.3, 0.8]).unwrap()
+    }
+
+    #[test]
+    fn madgwick_converges_to_level() {
+        let mut filter = Madgwick {
+            q: tilted(),
+            ..Default::default()
+        };
+        settle(|accel, gyro, dt| filter.update_imu(accel, gyro, dt));
+        assert_level(filter.quaternion());
+    }
+
+    #[test]
+    fn mahony_converges_to_level() {
+        let mut filter = Mahony {
+            q: tilted(),
+            ..Default::default()
+        };
+        settle(|accel, gyro, dt| filter.update_imu(accel, gyro, dt));
+        assert_level(filter.quaternion());
+    }
+
+    #[test]
+    fn constant_yaw_rate_integrates_to_yaw() {
+        // 28.65 dps for 2 s turns by 1 rad; the first sample only initializes the filters.
+        let turning = sample([0.0, 0.0, 1.0], [0.0, 0.0, 1.0f32.to_degrees() / 2.0]);
+        let mut madgwick = Madgwick::default();
+        let mut mahony = Mahony::default();
+        for _ in 0..=200 {
+            madgwick.update_at_rate(&turning, DataRate::Hz100);
+            mahony.update_at_rate(&turning, DataRate::Hz100);
+        }
+        assert_close(madgwick.quaternion().euler_angles(), [0.0, 0.0, 1.0]);
+        assert_close(mahony.quaternion().euler_angles(), [0.0, 0.0, 1.0]);
+    }
+}
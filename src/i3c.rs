@@ -0,0 +1,452 @@
+//! I3C interface configuration and transport.
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal::i2c::Operation;
+
+use crate::interface::BankState;
+use crate::{BankCache, Error, Icm42605, InterfaceError, InterruptEvent};
+
+/// I3C protocol configuration (`INTF_CONFIG4`, `INTF_CONFIG6`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct I3cConfig {
+    /// Only I3C devices are connected to the bus, disabling the I2C spike filter.
+    pub pure_bus: bool,
+    /// Accept single data rate transfers.
+    pub sdr: bool,
+    /// Accept HDR-DDR transfers.
+    pub ddr: bool,
+    /// Signal the enabled IBI sources with in-band interrupts.
+    pub ibi: bool,
+    /// Send a mandatory data byte with each in-band interrupt.
+    pub ibi_payload: bool,
+}
+
+impl Default for I3cConfig {
+    fn default() -> Self {
+        Self {
+            pure_bus: false,
+            sdr: true,
+            ddr: true,
+            ibi: true,
+            ibi_payload: true,
+        }
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Program the I3C protocol options: the pure-bus mode in `INTF_CONFIG4`, and the enabled
+    /// transfer modes and in-band interrupt settings in `INTF_CONFIG6`. I3C stays enabled as long
+    /// as either SDR or DDR is.
+    ///
+    /// The defaults of [`I3cConfig`] match the reset values, so this only needs to be called to
+    /// change them. A soft reset restores the defaults, so call it again after
+    /// [`soft_reset`](Self::soft_reset). When talking over I3C, disabling both transfer modes ends
+    /// communication with the device, and disabling the mode in use takes effect on the next
+    /// transfer.
+    pub async fn configure_i3c(&mut self, config: I3cConfig) -> Result<(), Error<I::Error>> {
+        self.user_bank_1()
+            .intf_config_4()
+            .modify_async(|r| r.set_i_3_c_bus_mode(config.pure_bus))
+            .await?;
+        self.user_bank_1()
+            .intf_config_6()
+            .modify_async(|r| {
+                r.set_i_3_c_en(config.sdr || config.ddr);
+                r.set_i_3_c_sdr_en(config.sdr);
+                r.set_i_3_c_ddr_en(config.ddr);
+                r.set_i_3_c_ibi_en(config.ibi);
+                r.set_i_3_c_ibi_byte_en(config.ibi_payload);
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Select the interrupt sources that raise in-band interrupts, replacing any previous
+    /// selection.
+    pub async fn set_ibi_sources(
+        &mut self,
+        events: &[InterruptEvent],
+    ) -> Result<(), Error<I::Error>> {
+        let has = |event| events.contains(&event);
+
+        self.user_bank_4()
+            .int_source_8()
+            .write_async(|r| {
+                r.set_fsync_ibi_en(has(InterruptEvent::Fsync));
+                r.set_pll_rdy_ibi_en(has(InterruptEvent::PllReady));
+                r.set_reset_down_ibi_en(has(InterruptEvent::ResetDone));
+                r.set_ui_drdy_ibi_en(has(InterruptEvent::DataReady));
+                r.set_fifo_ths_ibi_en(has(InterruptEvent::FifoThreshold));
+                r.set_fifo_full_ibi_en(has(InterruptEvent::FifoFull));
+                r.set_ui_agc_rdy_ibi_en(has(InterruptEvent::AgcReady));
+            })
+            .await?;
+        self.user_bank_4()
+            .int_source_9()
+            .write_async(|r| {
+                r.set_i_3_c_protocol_error_ibi_en(has(InterruptEvent::I3cProtocolError));
+                r.set_smd_ibi_en(has(InterruptEvent::SignificantMotion));
+                r.set_wom_x_ibi_en(has(InterruptEvent::WakeOnMotionX));
+                r.set_wom_y_ibi_en(has(InterruptEvent::WakeOnMotionY));
+                r.set_wom_z_ibi_en(has(InterruptEvent::WakeOnMotionZ));
+            })
+            .await?;
+        self.user_bank_4()
+            .int_source_10()
+            .write_async(|r| {
+                r.set_step_det_ibi_en(has(InterruptEvent::StepDetect));
+                r.set_step_cnt_ofl_ibi_en(has(InterruptEvent::StepCountOverflow));
+                r.set_tilt_det_ibi_en(has(InterruptEvent::Tilt));
+                r.set_wake_det_ibi_en(has(InterruptEvent::Wake));
+                r.set_sleep_det_ibi_en(has(InterruptEvent::Sleep));
+                r.set_tap_det_ibi_en(has(InterruptEvent::Tap));
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Private SDR transfers to an I3C target.
+///
+/// This mirrors the shape of `embedded_hal::i2c::I2c` until `embedded-hal` gains an I3C trait:
+/// adjacent operations of the same type are sent as one transfer, without a repeated start.
+/// Closures can be used through [`FnI3c`].
+pub trait I3c {
+    type Error;
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error>;
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(address, &mut [Operation::Write(write)])
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transaction(
+            address,
+            &mut [Operation::Write(write), Operation::Read(read)],
+        )
+    }
+}
+
+/// Asynchronous version of [`I3c`].
+#[allow(async_fn_in_trait)]
+pub trait AsyncI3c {
+    type Error;
+
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error>;
+
+    async fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(address, &mut [Operation::Write(write)])
+            .await
+    }
+
+    async fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.transaction(
+            address,
+            &mut [Operation::Write(write), Operation::Read(read)],
+        )
+        .await
+    }
+}
+
+/// Adapts a closure performing a transaction into an [`I3c`] or [`AsyncI3c`] transport.
+///
+/// The closure is called with the target address and the operations of the transaction.
+#[derive(Debug)]
+pub struct FnI3c<F>(pub F);
+
+impl<F, E> I3c for FnI3c<F>
+where
+    F: FnMut(u8, &mut [Operation<'_>]) -> Result<(), E>,
+{
+    type Error = E;
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        (self.0)(address, operations)
+    }
+}
+
+impl<F, E> AsyncI3c for FnI3c<F>
+where
+    F: FnMut(u8, &mut [Operation<'_>]) -> Result<(), E>,
+{
+    type Error = E;
+
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        (self.0)(address, operations)
+    }
+}
+
+/// Register interface over an I3C bus, using the dynamic address assigned to the device.
+pub struct Icm42605I3cInterface<T> {
+    i3c: T,
+    addr: u8,
+    bank: BankState,
+}
+
+impl<T> Icm42605I3cInterface<T> {
+    pub fn new(i3c: T, dynamic_address: u8) -> Self {
+        Self {
+            i3c,
+            addr: dynamic_address,
+            bank: BankState::new(),
+        }
+    }
+
+    /// Read back `REG_BANK_SEL` before every access, see
+    /// [`Icm42605Interface::with_bank_verification`].
+    ///
+    /// [`Icm42605Interface::with_bank_verification`]: crate::Icm42605Interface::with_bank_verification
+    pub fn with_bank_verification(mut self, verify: bool) -> Self {
+        self.bank.verify = verify;
+        self
+    }
+
+    fn set_bank(&mut self, bank: u8) -> Result<(), InterfaceError<T::Error>>
+    where
+        T: I3c,
+    {
+        self.bank.select(bank, |write, read| {
+            if read.is_empty() {
+                self.i3c.write(self.addr, write)
+            } else {
+                self.i3c.write_read(self.addr, write, read)
+            }
+        })
+    }
+
+    async fn set_bank_async(&mut self, bank: u8) -> Result<(), InterfaceError<T::Error>>
+    where
+        T: AsyncI3c,
+    {
+        self.bank
+            .select_async(bank, async |write, read| {
+                if read.is_empty() {
+                    self.i3c.write(self.addr, write).await
+                } else {
+                    self.i3c.write_read(self.addr, write, read).await
+                }
+            })
+            .await
+    }
+}
+
+impl<T> BankCache for Icm42605I3cInterface<T> {
+    fn invalidate_bank(&mut self) {
+        self.bank.invalidate();
+    }
+}
+
+impl<T: I3c> device_driver::RegisterInterface for Icm42605I3cInterface<T> {
+    type Error = InterfaceError<T::Error>;
+
+    type AddressType = u16;
+
+    fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let [reg, bank] = address.to_le_bytes();
+        self.set_bank(bank)?;
+
+        let result = self.i3c.transaction(
+            self.addr,
+            &mut [Operation::Write(&[reg]), Operation::Write(data)],
+        );
+        self.bank.complete(address, data, result)
+    }
+
+    fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let [reg, bank] = address.to_le_bytes();
+        self.set_bank(bank)?;
+        let result = self.i3c.write_read(self.addr, &[reg], data);
+        self.bank.complete(address, &[], result)
+    }
+}
+
+impl<T: AsyncI3c> device_driver::AsyncRegisterInterface for Icm42605I3cInterface<T> {
+    type Error = InterfaceError<T::Error>;
+
+    type AddressType = u16;
+
+    async fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let [reg, bank] = address.to_le_bytes();
+        self.set_bank_async(bank).await?;
+
+        let result = self
+            .i3c
+            .transaction(
+                self.addr,
+                &mut [Operation::Write(&[reg]), Operation::Write(data)],
+            )
+            .await;
+        self.bank.complete(address, data, result)
+    }
+
+    async fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let [reg, bank] = address.to_le_bytes();
+        self.set_bank_async(bank).await?;
+        let result = self.i3c.write_read(self.addr, &[reg], data).await;
+        self.bank.complete(address, &[], result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use device_driver::RegisterInterface;
+    use embassy_futures::block_on;
+    use embedded_hal::i2c::I2c;
+
+    use super::*;
+    use crate::REG_BANK_SEL;
+    use crate::mock::{Direction, MockDevice, MockError, Transaction};
+    use crate::registers::SIGNAL_PATH_RESET;
+
+    const DYNAMIC_ADDRESS: u8 = 0x0a;
+
+    /// An I3C transport that forwards private transfers to the I2C port of `mock`.
+    fn transport(
+        mock: &RefCell<MockDevice>,
+    ) -> impl I3c<Error = MockError> + AsyncI3c<Error = MockError> + '_ {
+        FnI3c(move |address, operations: &mut [Operation<'_>]| {
+            assert_eq!(address, DYNAMIC_ADDRESS);
+            I2c::transaction(&mut *mock.borrow_mut(), 0x68, operations)
+        })
+    }
+
+    fn bank_selects(mock: &RefCell<MockDevice>) -> usize {
+        mock.borrow()
+            .transactions()
+            .iter()
+            .filter(|t| t.register == REG_BANK_SEL && t.direction == Direction::Write)
+            .count()
+    }
+
+    #[test]
+    fn selects_bank_only_when_it_changes() {
+        let mock = RefCell::new(MockDevice::new(false));
+        let mut device =
+            Icm42605::new(Icm42605I3cInterface::new(transport(&mock), DYNAMIC_ADDRESS));
+
+        assert_eq!(
+            device.user_bank_0().who_am_i().read().unwrap().whoami(),
+            0x42
+        );
+        device.user_bank_1().sensor_config().read().unwrap();
+        device.user_bank_1().sensor_config().read().unwrap();
+        assert_eq!(mock.borrow().bank(), 1);
+        assert_eq!(bank_selects(&mock), 2);
+
+        block_on(device.user_bank_0().who_am_i().read_async()).unwrap();
+        assert_eq!(mock.borrow().bank(), 0);
+        assert_eq!(bank_selects(&mock), 3);
+    }
+
+    #[test]
+    fn writes_reach_the_selected_bank() {
+        let mock = RefCell::new(MockDevice::new(false));
+        let mut device =
+            Icm42605::new(Icm42605I3cInterface::new(transport(&mock), DYNAMIC_ADDRESS));
+
+        block_on(
+            device
+                .user_bank_4()
+                .offset_user_0()
+                .write_async(|r| r.set_gyro_x_offuser_lo(0x5a)),
+        )
+        .unwrap();
+        let last = mock.borrow().transactions().last().cloned();
+        assert_eq!(
+            last,
+            Some(Transaction {
+                direction: Direction::Write,
+                bank: 4,
+                register: 0x77,
+                data: std::vec![0x5a],
+            })
+        );
+    }
+
+    #[test]
+    fn reselects_bank_after_bus_error() {
+        let mock = RefCell::new(MockDevice::new(false));
+        let mut device =
+            Icm42605::new(Icm42605I3cInterface::new(transport(&mock), DYNAMIC_ADDRESS));
+
+        device.user_bank_1().sensor_config().read().unwrap();
+        let mut byte = [0];
+        assert!(matches!(
+            RegisterInterface::read_register(device.interface(), SIGNAL_PATH_RESET, 8, &mut byte),
+            Err(InterfaceError::Bus(MockError::WriteOnly(SIGNAL_PATH_RESET)))
+        ));
+        let selects = bank_selects(&mock);
+        device.user_bank_0().who_am_i().read().unwrap();
+        device.user_bank_0().who_am_i().read().unwrap();
+        assert_eq!(bank_selects(&mock), selects + 1);
+    }
+
+    #[test]
+    fn verification_detects_external_bank_switch() {
+        let mock = RefCell::new(MockDevice::new(false));
+        let mut device = Icm42605::new(
+            Icm42605I3cInterface::new(transport(&mock), DYNAMIC_ADDRESS)
+                .with_bank_verification(true),
+        );
+
+        device.user_bank_0().who_am_i().read().unwrap();
+        mock.borrow_mut().write(0, REG_BANK_SEL, &[2]).unwrap();
+        assert_eq!(
+            block_on(device.user_bank_0().who_am_i().read_async())
+                .unwrap()
+                .whoami(),
+            0x42
+        );
+        assert_eq!(mock.borrow().bank(), 0);
+    }
+}
@@ -0,0 +1,394 @@
+//! Serial interface selection and pad configuration.
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::registers::DEVICE_CONFIG;
+use crate::{
+    Error, Icm42605, Icm42605I3cInterface, Icm42605Interface, InterfaceError, REG_BANK_SEL,
+    UiSifsCfg,
+};
+
+/// Bits of `REG_BANK_SEL` holding the bank.
+const BANK_SEL_MASK: u8 = 0x07;
+
+/// A serial interface of the chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SerialInterface {
+    I2c,
+    I3c,
+    Spi,
+}
+
+/// Register interfaces that know which serial interface of the chip they talk to.
+pub trait SerialInterfaceKind {
+    const KIND: SerialInterface;
+}
+
+impl<I2C> SerialInterfaceKind for Icm42605Interface<I2C> {
+    const KIND: SerialInterface = SerialInterface::I2c;
+}
+
+impl<T> SerialInterfaceKind for Icm42605I3cInterface<T> {
+    const KIND: SerialInterface = SerialInterface::I3c;
+}
+
+/// Register interfaces that cache the selected register bank.
+pub trait BankCache {
+    /// Forget the cached bank, so that the next access selects its bank again.
+    fn invalidate_bank(&mut self);
+}
+
+impl<I2C> BankCache for Icm42605Interface<I2C> {
+    fn invalidate_bank(&mut self) {
+        self.bank.invalidate();
+    }
+}
+
+impl<I: BankCache> Icm42605<I> {
+    /// Select the register bank again before the next access, in case the chip was reset or
+    /// switched banks without the driver's knowledge.
+    pub fn resync_bank(&mut self) {
+        self.interface().invalidate_bank();
+    }
+}
+
+/// The bank selection state of a register interface.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BankState {
+    /// The bank last selected, or `None` if it is unknown.
+    bank: Option<u8>,
+    /// Read back `REG_BANK_SEL` before every access instead of trusting `bank`.
+    pub(crate) verify: bool,
+}
+
+impl BankState {
+    pub(crate) const fn new() -> Self {
+        Self {
+            bank: None,
+            verify: false,
+        }
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        self.bank = None;
+    }
+
+    /// Select `bank` before an access, unless it is known to be selected already.
+    ///
+    /// `transfer` writes its first argument and then reads into the second unless it is empty,
+    /// like [`FnI3c`](crate::FnI3c).
+    pub(crate) fn select<E>(
+        &mut self,
+        bank: u8,
+        mut transfer: impl FnMut(&[u8], &mut [u8]) -> Result<(), E>,
+    ) -> Result<(), InterfaceError<E>> {
+        if !self.needs_select(bank) {
+            return Ok(());
+        }
+        let result = if self.verify {
+            let mut actual = [0];
+            let mut select = || {
+                transfer(&[REG_BANK_SEL], &mut actual)?;
+                if actual[0] & BANK_SEL_MASK != bank {
+                    transfer(&[REG_BANK_SEL, bank], &mut [])?;
+                    transfer(&[REG_BANK_SEL], &mut actual)?;
+                }
+                Ok(actual[0])
+            };
+            select()
+        } else {
+            transfer(&[REG_BANK_SEL, bank], &mut []).map(|()| bank)
+        };
+        self.selected(bank, result)
+    }
+
+    /// Asynchronous version of [`BankState::select`].
+    pub(crate) async fn select_async<E>(
+        &mut self,
+        bank: u8,
+        mut transfer: impl AsyncFnMut(&[u8], &mut [u8]) -> Result<(), E>,
+    ) -> Result<(), InterfaceError<E>> {
+        if !self.needs_select(bank) {
+            return Ok(());
+        }
+        let result = if self.verify {
+            let mut actual = [0];
+            let mut select = async || {
+                transfer(&[REG_BANK_SEL], &mut actual).await?;
+                if actual[0] & BANK_SEL_MASK != bank {
+                    transfer(&[REG_BANK_SEL, bank], &mut []).await?;
+                    transfer(&[REG_BANK_SEL], &mut actual).await?;
+                }
+                Ok(actual[0])
+            };
+            select().await
+        } else {
+            transfer(&[REG_BANK_SEL, bank], &mut [])
+                .await
+                .map(|()| bank)
+        };
+        self.selected(bank, result)
+    }
+
+    /// Whether `bank` has to be selected before an access.
+    fn needs_select(&self, bank: u8) -> bool {
+        self.verify || self.bank != Some(bank)
+    }
+
+    /// Account for the outcome of selecting `bank`, which yields `REG_BANK_SEL` read back.
+    fn selected<E>(&mut self, bank: u8, result: Result<u8, E>) -> Result<(), InterfaceError<E>> {
+        let readback = self.complete(u16::from(REG_BANK_SEL), &[], result)?;
+        self.verify_selected(bank, readback)
+    }
+
+    /// Check the `REG_BANK_SEL` value read back after selecting `bank`.
+    fn verify_selected<E>(&mut self, bank: u8, readback: u8) -> Result<(), InterfaceError<E>> {
+        let actual = readback & BANK_SEL_MASK;
+        if actual != bank {
+            self.bank = None;
+            return Err(InterfaceError::BankMismatch {
+                expected: bank,
+                actual,
+            });
+        }
+        self.bank = Some(bank);
+        Ok(())
+    }
+
+    /// Account for the outcome of a register access that wrote `data` to `address`.
+    ///
+    /// The bank is unknown after a failed transfer, which may have been cut short while
+    /// selecting it, and after a soft reset, which returns the chip to bank 0. A soft reset is
+    /// detected in any write that covers `DEVICE_CONFIG`.
+    pub(crate) fn complete<T, E>(
+        &mut self,
+        address: u16,
+        data: &[u8],
+        result: Result<T, E>,
+    ) -> Result<T, InterfaceError<E>> {
+        let reset = address <= DEVICE_CONFIG
+            && data
+                .get(usize::from(DEVICE_CONFIG - address))
+                .is_some_and(|b| b & 0x01 != 0);
+        if result.is_err() || reset {
+            self.bank = None;
+        }
+        result.map_err(InterfaceError::Bus)
+    }
+}
+
+/// Output slew rate of a serial interface pad, named by its rise time range
+/// (`I2C_SLEW_RATE`, `SPI_SLEW_RATE`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum SlewRate {
+    Ns20To60,
+    Ns12To36,
+    Ns6To18,
+    Ns4To12,
+    Ns2To6,
+    NsBelow2,
+}
+
+impl SlewRate {
+    const ALL: [Self; 6] = [
+        Self::Ns20To60,
+        Self::Ns12To36,
+        Self::Ns6To18,
+        Self::Ns4To12,
+        Self::Ns2To6,
+        Self::NsBelow2,
+    ];
+
+    /// Minimum and maximum rise time in nanoseconds.
+    pub const fn rise_time_ns(self) -> (u32, u32) {
+        match self {
+            Self::Ns20To60 => (20, 60),
+            Self::Ns12To36 => (12, 36),
+            Self::Ns6To18 => (6, 18),
+            Self::Ns4To12 => (4, 12),
+            Self::Ns2To6 => (2, 6),
+            Self::NsBelow2 => (0, 2),
+        }
+    }
+
+    /// The slowest slew rate whose rise time range reaches down to `ns`.
+    pub fn from_rise_time_ns(ns: u32) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|rate| rate.rise_time_ns().0 <= ns)
+            .unwrap_or(Self::NsBelow2)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(bits)).copied()
+    }
+}
+
+/// Serial interface configuration (`INTF_CONFIG0`, `DRIVE_CONFIG`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InterfaceConfig {
+    /// Disable the serial interfaces not in use, so that traffic for other devices on shared
+    /// lines cannot be mistaken for commands.
+    pub lock: bool,
+    pub i2c_slew_rate: SlewRate,
+    pub spi_slew_rate: SlewRate,
+}
+
+impl Default for InterfaceConfig {
+    fn default() -> Self {
+        Self {
+            lock: true,
+            i2c_slew_rate: SlewRate::Ns20To60,
+            spi_slew_rate: SlewRate::NsBelow2,
+        }
+    }
+}
+
+impl<I> Icm42605<I>
+where
+    I: AsyncRegisterInterface<AddressType = u16> + SerialInterfaceKind,
+{
+    /// Apply `config` and read it back to verify it took effect, failing with
+    /// [`Error::InvalidConfig`] otherwise.
+    ///
+    /// When locking, the interface in use is determined by the register interface type.
+    pub async fn configure_interface(
+        &mut self,
+        config: InterfaceConfig,
+    ) -> Result<(), Error<I::Error>> {
+        let sifs = match (config.lock, I::KIND) {
+            (false, _) => UiSifsCfg::Reserved(0),
+            (true, SerialInterface::I2c | SerialInterface::I3c) => UiSifsCfg::DisableSpi,
+            (true, SerialInterface::Spi) => UiSifsCfg::DisableI2C,
+        };
+
+        self.user_bank_0()
+            .drive_config()
+            .write_async(|r| {
+                r.set_i_2_c_slew_rate(config.i2c_slew_rate as u8);
+                r.set_spi_slew_rate(config.spi_slew_rate as u8);
+            })
+            .await?;
+        self.user_bank_0()
+            .intf_config_0()
+            .modify_async(|r| r.set_ui_sifs_cfg(sifs))
+            .await?;
+
+        let drive = self.user_bank_0().drive_config().read_async().await?;
+        let intf = self.user_bank_0().intf_config_0().read_async().await?;
+        if SlewRate::from_bits(drive.i_2_c_slew_rate()) != Some(config.i2c_slew_rate)
+            || SlewRate::from_bits(drive.spi_slew_rate()) != Some(config.spi_slew_rate)
+            || intf.ui_sifs_cfg() != sifs
+        {
+            return Err(Error::InvalidConfig);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use device_driver::RegisterInterface;
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::RegisterInfo;
+    use crate::mock::{Direction, MockDevice, MockError, RegisterSpace, impl_interfaces};
+    use crate::registers::SIGNAL_PATH_RESET;
+
+    /// A mock device that ignores writes to reserved addresses, so that block writes can span
+    /// them.
+    struct Lenient(MockDevice);
+
+    impl RegisterSpace for Lenient {
+        fn i2c_address(&self) -> u8 {
+            self.0.i2c_address()
+        }
+
+        fn bank(&self) -> u8 {
+            self.0.bank()
+        }
+
+        fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError> {
+            self.0.read(bank, reg, data)
+        }
+
+        fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError> {
+            for (offset, &byte) in data.iter().enumerate() {
+                let reg = reg.wrapping_add(offset as u8);
+                let address = u16::from_be_bytes([bank, reg]);
+                if reg == REG_BANK_SEL || RegisterInfo::containing(address).is_some() {
+                    self.0.write(bank, reg, &[byte])?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl_interfaces!(Lenient);
+
+    fn bank_selects(mock: &MockDevice) -> usize {
+        mock.transactions()
+            .iter()
+            .filter(|t| t.register == REG_BANK_SEL && t.direction == Direction::Write)
+            .count()
+    }
+
+    #[test]
+    fn reselects_bank_after_bus_error() {
+        let mut device = Icm42605::new(Icm42605Interface::new(MockDevice::new(false), false));
+
+        device.user_bank_1().sensor_config().read().unwrap();
+        let mut byte = [0];
+        assert!(matches!(
+            RegisterInterface::read_register(device.interface(), SIGNAL_PATH_RESET, 8, &mut byte),
+            Err(InterfaceError::Bus(MockError::WriteOnly(SIGNAL_PATH_RESET)))
+        ));
+        let selects = bank_selects(&device.interface().i2c);
+        device.user_bank_0().who_am_i().read().unwrap();
+        device.user_bank_0().who_am_i().read().unwrap();
+        assert_eq!(bank_selects(&device.interface().i2c), selects + 1);
+    }
+
+    #[test]
+    fn block_writes_covering_device_config_reset_the_bank() {
+        let mut device = Icm42605::new(Icm42605Interface::new(
+            Lenient(MockDevice::new(false)),
+            false,
+        ));
+
+        device.user_bank_0().who_am_i().read().unwrap();
+        RegisterInterface::write_register(device.interface(), DEVICE_CONFIG - 1, 16, &[0, 1])
+            .unwrap();
+        let selects = bank_selects(&device.interface().i2c.0);
+        device.user_bank_0().who_am_i().read().unwrap();
+        assert_eq!(bank_selects(&device.interface().i2c.0), selects + 1);
+
+        // Writes that only start or end next to it leave the bank alone.
+        RegisterInterface::write_register(device.interface(), DEVICE_CONFIG + 1, 16, &[1, 0])
+            .unwrap();
+        device.user_bank_0().who_am_i().read().unwrap();
+        assert_eq!(bank_selects(&device.interface().i2c.0), selects + 1);
+    }
+
+    #[test]
+    fn verification_detects_external_bank_switch() {
+        let mut device = Icm42605::new(
+            Icm42605Interface::new(MockDevice::new(false), false).with_bank_verification(true),
+        );
+
+        device.user_bank_0().who_am_i().read().unwrap();
+        device.interface().i2c.write(0, REG_BANK_SEL, &[2]).unwrap();
+        assert_eq!(
+            block_on(device.user_bank_0().who_am_i().read_async())
+                .unwrap()
+                .whoami(),
+            0x42
+        );
+        assert_eq!(device.interface().i2c.bank(), 0);
+    }
+}
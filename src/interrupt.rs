@@ -0,0 +1,89 @@
+//! Interrupt outputs, interrupt sources and waiting on the interrupt pins.
+
+use core::convert::Infallible;
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::digital::Wait;
+
+use crate::{Error, Icm42605};
+
+/// One of the two interrupt outputs of the chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterruptPin {
+    Int1,
+    Int2,
+}
+
+/// A source of interrupts, as reported in the `INT_STATUS` registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum InterruptEvent {
+    Fsync,
+    PllReady,
+    ResetDone,
+    DataReady,
+    FifoThreshold,
+    FifoFull,
+    AgcReady,
+    I3cProtocolError,
+    SignificantMotion,
+    WakeOnMotionX,
+    WakeOnMotionY,
+    WakeOnMotionZ,
+    StepDetect,
+    StepCountOverflow,
+    Tilt,
+    Wake,
+    Sleep,
+    Tap,
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Wait until `pin` is at the asserted level configured for `int` in `INT_CONFIG`.
+    pub(crate) async fn wait_for_interrupt<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<(), Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        self.wait_for_interrupt_level(int, pin, true).await
+    }
+
+    /// Wait until `pin` is at the deasserted level configured for `int` in `INT_CONFIG`.
+    pub(crate) async fn wait_for_interrupt_deasserted<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+    ) -> Result<(), Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        self.wait_for_interrupt_level(int, pin, false).await
+    }
+
+    async fn wait_for_interrupt_level<P>(
+        &mut self,
+        int: InterruptPin,
+        pin: &mut P,
+        asserted: bool,
+    ) -> Result<(), Error<I::Error>>
+    where
+        P: Wait<Error = Infallible>,
+    {
+        let config = self.user_bank_0().int_config().read_async().await?;
+        let active_high = match int {
+            InterruptPin::Int1 => config.int_1_polarity(),
+            InterruptPin::Int2 => config.int_2_polarity(),
+        };
+
+        let Ok(()) = if active_high == asserted {
+            pin.wait_for_high().await
+        } else {
+            pin.wait_for_low().await
+        };
+        Ok(())
+    }
+}
@@ -2,6 +2,61 @@
 
 //! An embedded async driver for the ICM-42605 6-Axis IMU.
 
+#[cfg(any(test, feature = "mock"))]
+extern crate std;
+
+mod apex;
+mod array;
+mod axes;
+mod block;
+mod data_ready;
+mod device;
+mod dump;
+mod error;
+mod fifo;
+mod fsync;
+#[cfg(feature = "fusion")]
+pub mod fusion;
+mod i3c;
+mod interface;
+mod interrupt;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+mod orientation;
+mod power;
+mod rate;
+mod record;
+mod registers;
+mod sample;
+#[cfg(any(test, feature = "mock"))]
+pub mod sim;
+mod snapshot;
+mod timestamp;
+
+pub use apex::{
+    MountingMatrix, RaiseToWake, RaiseToWakeEvent, SleepGestureDelay, SleepTimeout, TiltEvent,
+    TiltWaitTime,
+};
+pub use array::{ArrayReading, ImuArray, ImuHealth, Tolerance};
+pub use axes::AxisMask;
+pub use data_ready::{DataReadyStream, RawSample};
+pub use dump::{RegisterDump, RegisterValue};
+pub use error::{Error, InterfaceError};
+pub use fifo::{FifoHeader, FifoItem, FifoManager, FifoPacket, FifoTimestampKind};
+pub use fsync::FsyncEvent;
+pub use i3c::{AsyncI3c, FnI3c, I3c, I3cConfig, Icm42605I3cInterface};
+pub use interface::{BankCache, InterfaceConfig, SerialInterface, SerialInterfaceKind, SlewRate};
+pub use interrupt::{InterruptEvent, InterruptPin};
+pub use orientation::{MountingOrientation, SignedAxis, UserOffsets};
+pub use power::{LowPowerAveraging, PowerManager, accel_low_power_current_ua};
+pub use record::{Record, RecordKind, RecordSink, Recorder, Replay, ReplayError};
+pub use registers::{REGISTERS, RegisterAccess, RegisterInfo};
+pub use sample::{DataRates, FullScale, ScaledSample};
+pub use snapshot::{ConfigSnapshot, RegisterChange};
+pub use timestamp::{
+    FifoTimestamp, FifoTimestampTracker, TimestampClock, TimestampConfig, TimestampResolution,
+};
+
 device_driver::create_device! {
     device_name: Icm42605,
     dsl: {
@@ -459,7 +514,10 @@ device_driver::create_device! {
                     AccelZ,
                 } = 4..7,
                 fsync_ui_flag_clear_sel: bool = 1,
-                fsync_polarity: bool = 0,
+                fsync_polarity: uint as enum FsyncPolarity {
+                    RisingEdge,
+                    FallingEdge,
+                } = 0..1,
             },
 
             register IntConfig0 {
@@ -561,6 +619,7 @@ device_driver::create_device! {
                 type Access = RO;
                 const ADDRESS = 0x75;
                 const SIZE_BITS = 8;
+                const RESET_VALUE = 0x42;
 
                 whoami: uint = 0..8,
             },
@@ -936,12 +995,15 @@ device_driver::create_device! {
     }
 }
 
+use embedded_hal::i2c::Operation;
+use interface::BankState;
+
 const REG_BANK_SEL: u8 = 0x76;
 
 pub struct Icm42605Interface<I2C> {
     i2c: I2C,
     addr: u8,
-    bank: u8,
+    bank: BankState,
 }
 
 impl<I2C> Icm42605Interface<I2C> {
@@ -949,35 +1011,52 @@ impl<I2C> Icm42605Interface<I2C> {
         Self {
             i2c,
             addr: 0x68 | u8::from(ad0),
-            bank: 0xff,
+            bank: BankState::new(),
         }
     }
 
-    fn set_bank(&mut self, bank: u8) -> Result<(), I2C::Error>
+    /// Read back `REG_BANK_SEL` before every access and select the bank whenever it differs,
+    /// instead of trusting the cached bank.
+    ///
+    /// This guards against the chip being reset or switched to another bank behind the driver's
+    /// back, at the cost of an extra read per access. Accesses fail with
+    /// [`InterfaceError::BankMismatch`] if selecting the bank does not take effect.
+    pub fn with_bank_verification(mut self, verify: bool) -> Self {
+        self.bank.verify = verify;
+        self
+    }
+
+    fn set_bank(&mut self, bank: u8) -> Result<(), InterfaceError<I2C::Error>>
     where
         I2C: embedded_hal::i2c::I2c,
     {
-        if bank != self.bank {
-            self.i2c.write(self.addr, &[REG_BANK_SEL, bank])?;
-            self.bank = bank;
-        }
-        Ok(())
+        self.bank.select(bank, |write, read| {
+            if read.is_empty() {
+                self.i2c.write(self.addr, write)
+            } else {
+                self.i2c.write_read(self.addr, write, read)
+            }
+        })
     }
 
-    async fn set_bank_async(&mut self, bank: u8) -> Result<(), I2C::Error>
+    async fn set_bank_async(&mut self, bank: u8) -> Result<(), InterfaceError<I2C::Error>>
     where
         I2C: embedded_hal_async::i2c::I2c,
     {
-        if bank != self.bank {
-            self.i2c.write(self.addr, &[REG_BANK_SEL, bank]).await?;
-            self.bank = bank;
-        }
-        Ok(())
+        self.bank
+            .select_async(bank, async |write, read| {
+                if read.is_empty() {
+                    self.i2c.write(self.addr, write).await
+                } else {
+                    self.i2c.write_read(self.addr, write, read).await
+                }
+            })
+            .await
     }
 }
 
 impl<I2C: embedded_hal::i2c::I2c> device_driver::RegisterInterface for Icm42605Interface<I2C> {
-    type Error = I2C::Error;
+    type Error = InterfaceError<I2C::Error>;
 
     type AddressType = u16;
 
@@ -987,16 +1066,15 @@ impl<I2C: embedded_hal::i2c::I2c> device_driver::RegisterInterface for Icm42605I
         _size_bits: u32,
         data: &[u8],
     ) -> Result<(), Self::Error> {
-        const MAX_LEN: usize = 3;
-        assert!(data.len() < MAX_LEN);
-
         let [reg, bank] = address.to_le_bytes();
         self.set_bank(bank)?;
 
-        let mut buf = heapless::Vec::<u8, MAX_LEN>::new();
-        let _ = buf.push(reg);
-        let _ = buf.extend_from_slice(data);
-        self.i2c.write(self.addr, &buf)
+        // Adjacent writes are sent without a repeated start, so no buffer is needed.
+        let result = self.i2c.transaction(
+            self.addr,
+            &mut [Operation::Write(&[reg]), Operation::Write(data)],
+        );
+        self.bank.complete(address, data, result)
     }
 
     fn read_register(
@@ -1007,14 +1085,15 @@ impl<I2C: embedded_hal::i2c::I2c> device_driver::RegisterInterface for Icm42605I
     ) -> Result<(), Self::Error> {
         let [reg, bank] = address.to_le_bytes();
         self.set_bank(bank)?;
-        self.i2c.write_read(self.addr, &[reg], data)
+        let result = self.i2c.write_read(self.addr, &[reg], data);
+        self.bank.complete(address, &[], result)
     }
 }
 
 impl<I2C: embedded_hal_async::i2c::I2c> device_driver::AsyncRegisterInterface
     for Icm42605Interface<I2C>
 {
-    type Error = I2C::Error;
+    type Error = InterfaceError<I2C::Error>;
 
     type AddressType = u16;
 
@@ -1024,16 +1103,18 @@ impl<I2C: embedded_hal_async::i2c::I2c> device_driver::AsyncRegisterInterface
         _size_bits: u32,
         data: &[u8],
     ) -> Result<(), Self::Error> {
-        const MAX_LEN: usize = 3;
-        assert!(data.len() < MAX_LEN);
-
         let [reg, bank] = address.to_le_bytes();
         self.set_bank_async(bank).await?;
 
-        let mut buf = heapless::Vec::<u8, MAX_LEN>::new();
-        let _ = buf.push(reg);
-        let _ = buf.extend_from_slice(data);
-        self.i2c.write(self.addr, &buf).await
+        // Adjacent writes are sent without a repeated start, so no buffer is needed.
+        let result = self
+            .i2c
+            .transaction(
+                self.addr,
+                &mut [Operation::Write(&[reg]), Operation::Write(data)],
+            )
+            .await;
+        self.bank.complete(address, data, result)
     }
 
     async fn read_register(
@@ -1044,6 +1125,7 @@ impl<I2C: embedded_hal_async::i2c::I2c> device_driver::AsyncRegisterInterface
     ) -> Result<(), Self::Error> {
         let [reg, bank] = address.to_le_bytes();
         self.set_bank_async(bank).await?;
-        self.i2c.write_read(self.addr, &[reg], data).await
+        let result = self.i2c.write_read(self.addr, &[reg], data).await;
+        self.bank.complete(address, &[], result)
     }
 }
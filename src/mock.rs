@@ -0,0 +1,617 @@
+//! In-memory register file for testing code that uses the driver off-target.
+//!
+//! [`MockDevice`] can be used directly as the register interface of an [`Icm42605`], or as the
+//! I2C bus of an [`Icm42605Interface`] so that bank selection goes through `REG_BANK_SEL`.
+//!
+//! [`Icm42605`]: crate::Icm42605
+//! [`Icm42605Interface`]: crate::Icm42605Interface
+
+use std::vec::Vec;
+
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource, Operation};
+
+use crate::registers::{DEVICE_CONFIG, INT_STATUS};
+use crate::{REG_BANK_SEL, RegisterAccess, RegisterInfo, field_sets};
+
+const BANKS: usize = 5;
+
+/// Error returned by [`MockDevice`] for accesses the real device would not honor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockError {
+    /// The I2C address does not match the device.
+    Address(u8),
+    /// The address, with the bank in the upper byte, is not part of any register.
+    Unmapped(u16),
+    /// Write to a read-only register.
+    ReadOnly(u16),
+    /// Read from a write-only register.
+    WriteOnly(u16),
+}
+
+impl embedded_hal::i2c::Error for MockError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Address(_) => ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Direction of a logged [`Transaction`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+/// A register access performed on a [`MockDevice`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub direction: Direction,
+    pub bank: u8,
+    pub register: u8,
+    pub data: Vec<u8>,
+}
+
+/// An in-memory ICM-42605 register file.
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    addr: u8,
+    bank: u8,
+    registers: [[u8; 256]; BANKS],
+    log: Vec<Transaction>,
+}
+
+impl MockDevice {
+    /// Create a device answering at the I2C address selected by `ad0`, with all registers at
+    /// their reset values.
+    pub fn new(ad0: bool) -> Self {
+        let mut mock = Self {
+            addr: 0x68 | u8::from(ad0),
+            bank: 0,
+            registers: [[0; 256]; BANKS],
+            log: Vec::new(),
+        };
+        mock.reset();
+        mock
+    }
+
+    /// Restore all registers to their reset values and select bank 0.
+    ///
+    /// The transaction log is kept.
+    pub fn reset(&mut self) {
+        self.bank = 0;
+        self.registers = [[0; 256]; BANKS];
+        for reg in crate::REGISTERS {
+            let [bank, offset] = reg.address.to_be_bytes();
+            let offset = usize::from(offset);
+            reg.reset_value(&mut self.registers[usize::from(bank)][offset..]);
+        }
+    }
+
+    /// The currently selected bank.
+    pub fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    /// Read register bytes without access checks or logging.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes extend past the last bank, bank 4.
+    pub fn peek(&self, address: u16, data: &mut [u8]) {
+        for (address, byte) in (address..).zip(data) {
+            *byte = *self.cell(address);
+        }
+    }
+
+    /// Write register bytes without access checks or logging.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes extend past the last bank, bank 4.
+    pub fn poke(&mut self, address: u16, data: &[u8]) {
+        for (address, byte) in (address..).zip(data) {
+            *self.cell_mut(address) = *byte;
+        }
+    }
+
+    /// All register accesses performed so far, oldest first.
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.log
+    }
+
+    pub fn clear_transactions(&mut self) {
+        self.log.clear();
+    }
+
+    /// Read `data.len()` bytes starting at `reg` in `bank`, as the device would over the bus.
+    pub fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError> {
+        for (offset, byte) in data.iter_mut().enumerate() {
+            let address = u16::from_be_bytes([bank, reg.wrapping_add(offset as u8)]);
+            *byte = match address.to_be_bytes()[1] {
+                REG_BANK_SEL => self.bank,
+                _ => {
+                    let info = Self::info(address)?;
+                    if !info.access.is_readable() {
+                        return Err(MockError::WriteOnly(address));
+                    }
+                    *self.cell(address)
+                }
+            };
+        }
+
+        // Clear status flags only once the whole read succeeded.
+        for offset in 0..data.len() {
+            let address = u16::from_be_bytes([bank, reg.wrapping_add(offset as u8)]);
+            if let Some(info) = RegisterInfo::containing(address)
+                && info.access == RegisterAccess::ReadClear
+            {
+                *self.cell_mut(address) = 0;
+            }
+        }
+
+        self.log.push(Transaction {
+            direction: Direction::Read,
+            bank,
+            register: reg,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    /// Write `data` starting at `reg` in `bank`, as the device would over the bus.
+    ///
+    /// A write to `REG_BANK_SEL` selects the bank for subsequent bus accesses, and setting
+    /// `SOFT_RESET_CONFIG` resets the device and raises `RESET_DONE_INT`.
+    pub fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError> {
+        for offset in 0..data.len() {
+            let address = u16::from_be_bytes([bank, reg.wrapping_add(offset as u8)]);
+            if address.to_be_bytes()[1] != REG_BANK_SEL
+                && !Self::info(address)?.access.is_writable()
+            {
+                return Err(MockError::ReadOnly(address));
+            }
+        }
+
+        for (offset, &byte) in data.iter().enumerate() {
+            let address = u16::from_be_bytes([bank, reg.wrapping_add(offset as u8)]);
+            match address.to_be_bytes()[1] {
+                REG_BANK_SEL => self.bank = byte,
+                _ => *self.cell_mut(address) = byte,
+            }
+        }
+
+        let mut device_config = [0];
+        self.peek(DEVICE_CONFIG, &mut device_config);
+        if field_sets::DeviceConfig::from(device_config).soft_reset_config() {
+            self.reset();
+            self.poke(INT_STATUS, &[1 << 4]);
+        }
+
+        self.log.push(Transaction {
+            direction: Direction::Write,
+            bank,
+            register: reg,
+            data: data.to_vec(),
+        });
+        Ok(())
+    }
+
+    fn info(address: u16) -> Result<&'static RegisterInfo, MockError> {
+        RegisterInfo::containing(address).ok_or(MockError::Unmapped(address))
+    }
+
+    fn cell(&self, address: u16) -> &u8 {
+        let [bank, offset] = address.to_be_bytes();
+        &self.registers[usize::from(bank)][usize::from(offset)]
+    }
+
+    fn cell_mut(&mut self, address: u16) -> &mut u8 {
+        let [bank, offset] = address.to_be_bytes();
+        &mut self.registers[usize::from(bank)][usize::from(offset)]
+    }
+}
+
+/// Bus-level access to a register file, shared by the interface implementations of the mock
+/// and the simulator.
+pub(crate) trait RegisterSpace {
+    /// The I2C address the device answers at.
+    fn i2c_address(&self) -> u8;
+
+    /// The bank selected through `REG_BANK_SEL`.
+    fn bank(&self) -> u8;
+
+    fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError>;
+
+    fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError>;
+
+    fn i2c_transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), MockError> {
+        if address != self.i2c_address() {
+            return Err(MockError::Address(address));
+        }
+
+        // The first written byte sets the register pointer, which auto-increments.
+        let mut pointer = None::<u8>;
+        for op in operations {
+            match op {
+                Operation::Write(data) => {
+                    let data = match pointer {
+                        Some(_) => &data[..],
+                        None => match data.split_first() {
+                            Some((&reg, rest)) => {
+                                pointer = Some(reg);
+                                rest
+                            }
+                            None => continue,
+                        },
+                    };
+                    if let Some(reg) = pointer.filter(|_| !data.is_empty()) {
+                        self.write(self.bank(), reg, data)?;
+                        pointer = Some(reg.wrapping_add(data.len() as u8));
+                    }
+                }
+                Operation::Read(data) => {
+                    let reg = pointer.unwrap_or(0);
+                    self.read(self.bank(), reg, data)?;
+                    pointer = Some(reg.wrapping_add(data.len() as u8));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RegisterSpace for MockDevice {
+    fn i2c_address(&self) -> u8 {
+        self.addr
+    }
+
+    fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError> {
+        MockDevice::read(self, bank, reg, data)
+    }
+
+    fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError> {
+        MockDevice::write(self, bank, reg, data)
+    }
+}
+
+/// Implement the I2C and register interface traits for a [`RegisterSpace`].
+///
+/// Register interface accesses address the bank directly instead of going through
+/// `REG_BANK_SEL`.
+macro_rules! impl_interfaces {
+    ($ty:ty $(, $param:ident: $bound:path)?) => {
+        impl$(<$param: $bound>)? embedded_hal::i2c::ErrorType for $ty {
+            type Error = $crate::mock::MockError;
+        }
+
+        impl$(<$param: $bound>)? embedded_hal::i2c::I2c for $ty {
+            fn transaction(
+                &mut self,
+                address: u8,
+                operations: &mut [embedded_hal::i2c::Operation<'_>],
+            ) -> Result<(), Self::Error> {
+                $crate::mock::RegisterSpace::i2c_transaction(self, address, operations)
+            }
+        }
+
+        impl$(<$param: $bound>)? embedded_hal_async::i2c::I2c for $ty {
+            async fn transaction(
+                &mut self,
+                address: u8,
+                operations: &mut [embedded_hal::i2c::Operation<'_>],
+            ) -> Result<(), Self::Error> {
+                $crate::mock::RegisterSpace::i2c_transaction(self, address, operations)
+            }
+        }
+
+        impl$(<$param: $bound>)? $crate::SerialInterfaceKind for $ty {
+            const KIND: $crate::SerialInterface = $crate::SerialInterface::I2c;
+        }
+
+        impl$(<$param: $bound>)? device_driver::RegisterInterface for $ty {
+            type Error = $crate::mock::MockError;
+
+            type AddressType = u16;
+
+            fn write_register(
+                &mut self,
+                address: Self::AddressType,
+                _size_bits: u32,
+                data: &[u8],
+            ) -> Result<(), Self::Error> {
+                let [bank, reg] = address.to_be_bytes();
+                $crate::mock::RegisterSpace::write(self, bank, reg, data)
+            }
+
+            fn read_register(
+                &mut self,
+                address: Self::AddressType,
+                _size_bits: u32,
+                data: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                let [bank, reg] = address.to_be_bytes();
+                $crate::mock::RegisterSpace::read(self, bank, reg, data)
+            }
+        }
+
+        impl$(<$param: $bound>)? device_driver::AsyncRegisterInterface for $ty {
+            type Error = $crate::mock::MockError;
+
+            type AddressType = u16;
+
+            async fn write_register(
+                &mut self,
+                address: Self::AddressType,
+                size_bits: u32,
+                data: &[u8],
+            ) -> Result<(), Self::Error> {
+                device_driver::RegisterInterface::write_register(self, address, size_bits, data)
+            }
+
+            async fn read_register(
+                &mut self,
+                address: Self::AddressType,
+                size_bits: u32,
+                data: &mut [u8],
+            ) -> Result<(), Self::Error> {
+                device_driver::RegisterInterface::read_register(self, address, size_bits, data)
+            }
+        }
+    };
+}
+pub(crate) use impl_interfaces;
+
+impl_interfaces!(MockDevice);
+
+#[cfg(test)]
+mod tests {
+    use device_driver::RegisterInterface;
+    use embassy_futures::block_on;
+    use std::vec;
+
+    use super::*;
+    use crate::{DataRate, Icm42605, Icm42605Interface, InterfaceError, REGISTERS};
+
+    /// A delay that returns immediately; the mock completes everything synchronously.
+    struct NoDelay;
+
+    impl embedded_hal_async::delay::DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn transaction(direction: Direction, bank: u8, register: u8, data: &[u8]) -> Transaction {
+        Transaction {
+            direction,
+            bank,
+            register,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn registers_start_at_reset_values() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+
+        assert_eq!(
+            device.user_bank_0().who_am_i().read().unwrap().whoami(),
+            0x42
+        );
+        assert_eq!(
+            device
+                .user_bank_0()
+                .gyro_config_0()
+                .read()
+                .unwrap()
+                .gyro_odr(),
+            DataRate::Hz1000
+        );
+        for info in REGISTERS.iter().filter(|info| info.access.is_readable()) {
+            let mut expected = [0; 4];
+            info.reset_value(&mut expected);
+            let mut actual = [0; 4];
+            device
+                .interface()
+                .read_register(
+                    info.address,
+                    (info.size_bytes * 8) as u32,
+                    &mut actual[..info.size_bytes],
+                )
+                .unwrap();
+            assert_eq!(actual, expected, "{}", info.name);
+        }
+    }
+
+    #[test]
+    fn bus_accesses_select_banks_through_reg_bank_sel() {
+        let mut mock = MockDevice::new(false);
+        {
+            let mut device = Icm42605::new(Icm42605Interface::new(&mut mock, false));
+            device
+                .user_bank_1()
+                .sensor_config()
+                .write(|r| {
+                    r.set_xa_disable(true);
+                    r.set_ya_disable(true);
+                    r.set_za_disable(true);
+                })
+                .unwrap();
+            device
+                .user_bank_1()
+                .sensor_config()
+                .modify(|r| r.set_za_disable(false))
+                .unwrap();
+            device.user_bank_0().who_am_i().read().unwrap();
+        }
+
+        assert_eq!(mock.bank(), 0);
+        assert_eq!(
+            mock.transactions(),
+            [
+                transaction(Direction::Write, 0, REG_BANK_SEL, &[1]),
+                transaction(Direction::Write, 1, 0x03, &[0x07]),
+                transaction(Direction::Read, 1, 0x03, &[0x07]),
+                transaction(Direction::Write, 1, 0x03, &[0x03]),
+                transaction(Direction::Write, 1, REG_BANK_SEL, &[0]),
+                transaction(Direction::Read, 0, 0x75, &[0x42]),
+            ]
+        );
+        let mut value = [0];
+        mock.peek(0x0103, &mut value);
+        assert_eq!(value, [0x03]);
+    }
+
+    #[test]
+    fn writes_24_bit_registers_in_one_transaction() {
+        const GYRO_CONFIG_STATIC3: u16 = 0x010c;
+        const ACCEL_CONFIG_STATIC2: u16 = 0x020c;
+
+        let mut mock = MockDevice::new(false);
+        {
+            let mut interface = Icm42605Interface::new(&mut mock, false);
+            RegisterInterface::write_register(
+                &mut interface,
+                GYRO_CONFIG_STATIC3,
+                24,
+                &[0x01, 0x02, 0x03],
+            )
+            .unwrap();
+            block_on(device_driver::AsyncRegisterInterface::write_register(
+                &mut interface,
+                ACCEL_CONFIG_STATIC2,
+                24,
+                &[0x04, 0x05, 0x06],
+            ))
+            .unwrap();
+        }
+
+        let mut value = [0; 3];
+        mock.peek(GYRO_CONFIG_STATIC3, &mut value);
+        assert_eq!(value, [0x01, 0x02, 0x03]);
+        mock.peek(ACCEL_CONFIG_STATIC2, &mut value);
+        assert_eq!(value, [0x04, 0x05, 0x06]);
+        assert_eq!(
+            mock.transactions()[1],
+            transaction(Direction::Write, 1, 0x0c, &[0x01, 0x02, 0x03])
+        );
+    }
+
+    #[test]
+    fn rejects_accesses_the_device_would_not_honor() {
+        let mut mock = MockDevice::new(false);
+        let mut who_am_i = [0];
+        mock.peek(0x0075, &mut who_am_i);
+
+        assert_eq!(
+            mock.write_register(0x0075, 8, &[0]),
+            Err(MockError::ReadOnly(0x0075))
+        );
+        assert_eq!(
+            mock.read_register(0x004b, 8, &mut [0]),
+            Err(MockError::WriteOnly(0x004b))
+        );
+        assert_eq!(
+            mock.read_register(0x0000, 8, &mut [0]),
+            Err(MockError::Unmapped(0x0000))
+        );
+        // A burst that runs past a register is rejected as a whole.
+        assert_eq!(
+            mock.write_register(0x0014, 16, &[0x03, 0]),
+            Err(MockError::Unmapped(0x0015))
+        );
+        let mut int_config = [0];
+        mock.peek(0x0014, &mut int_config);
+        assert_eq!(int_config, [0]);
+
+        let mut after = [0];
+        mock.peek(0x0075, &mut after);
+        assert_eq!(after, who_am_i);
+        assert!(mock.transactions().is_empty());
+
+        let mut device = Icm42605::new(Icm42605Interface::new(&mut mock, true));
+        assert!(matches!(
+            device.user_bank_0().who_am_i().read(),
+            Err(InterfaceError::Bus(MockError::Address(0x69)))
+        ));
+    }
+
+    #[test]
+    fn status_registers_clear_on_read() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        device.interface().poke(INT_STATUS, &[0x08]);
+
+        assert!(
+            device
+                .user_bank_0()
+                .int_status()
+                .read()
+                .unwrap()
+                .data_rdy_int()
+        );
+        assert!(
+            !device
+                .user_bank_0()
+                .int_status()
+                .read()
+                .unwrap()
+                .data_rdy_int()
+        );
+    }
+
+    #[test]
+    fn soft_reset_restores_reset_values() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        block_on(
+            device
+                .user_bank_0()
+                .gyro_config_0()
+                .modify_async(|r| r.set_gyro_odr(DataRate::Hz200)),
+        )
+        .unwrap();
+        device.interface().write(1, 0x03, &[0x07]).unwrap();
+
+        block_on(device.soft_reset(&mut NoDelay)).unwrap();
+
+        assert_eq!(
+            device
+                .user_bank_0()
+                .gyro_config_0()
+                .read()
+                .unwrap()
+                .gyro_odr(),
+            DataRate::Hz1000
+        );
+        let mut sensor_config = [0];
+        device.interface().peek(0x0103, &mut sensor_config);
+        assert_eq!(sensor_config, [0]);
+    }
+
+    #[test]
+    fn logs_transactions_until_cleared() {
+        let mut mock = MockDevice::new(false);
+        mock.write(0, 0x14, &[0x03]).unwrap();
+        let mut data = [0; 2];
+        mock.read(4, 0x77, &mut data).unwrap();
+
+        assert_eq!(
+            mock.transactions(),
+            [
+                transaction(Direction::Write, 0, 0x14, &[0x03]),
+                transaction(Direction::Read, 4, 0x77, &[0, 0]),
+            ]
+        );
+        mock.clear_transactions();
+        assert_eq!(mock.transactions(), vec![]);
+        mock.reset();
+        assert!(mock.transactions().is_empty());
+    }
+}
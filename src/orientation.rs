@@ -0,0 +1,385 @@
+//! Mounting orientation of the sensor on the board.
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::registers::OFFSET_USER0;
+use crate::{Error, FifoPacket, Icm42605, MountingMatrix, RawSample, ScaledSample};
+
+/// Resolution of the gyroscope user offsets in degrees per second.
+const GYRO_OFFSET_DPS: f32 = 1.0 / 32.0;
+/// Resolution of the accelerometer user offsets in g.
+const ACCEL_OFFSET_G: f32 = 1.0 / 2000.0;
+
+/// A sensor axis, possibly reversed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SignedAxis {
+    X,
+    NegX,
+    Y,
+    NegY,
+    Z,
+    NegZ,
+}
+
+impl SignedAxis {
+    const ALL: [Self; 6] = [
+        Self::X,
+        Self::NegX,
+        Self::Y,
+        Self::NegY,
+        Self::Z,
+        Self::NegZ,
+    ];
+
+    const fn unit(self) -> [i8; 3] {
+        match self {
+            Self::X => [1, 0, 0],
+            Self::NegX => [-1, 0, 0],
+            Self::Y => [0, 1, 0],
+            Self::NegY => [0, -1, 0],
+            Self::Z => [0, 0, 1],
+            Self::NegZ => [0, 0, -1],
+        }
+    }
+}
+
+/// How the sensor is mounted relative to the body frame of the product.
+///
+/// The orientation is a 3x3 integer matrix `M` that maps sensor-frame vectors to body-frame
+/// vectors, `body = M * sensor`. Any integer matrix may be used to transform samples, but only
+/// the 24 proper rotations in [`MountingOrientation::ROTATIONS`] can be inverted to program
+/// offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MountingOrientation {
+    matrix: [[i8; 3]; 3],
+}
+
+impl Default for MountingOrientation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl From<MountingMatrix> for MountingOrientation {
+    fn from(matrix: MountingMatrix) -> Self {
+        Self::from_matrix(match matrix {
+            MountingMatrix::XYZ => [[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+            MountingMatrix::XNegYNegZ => [[1, 0, 0], [0, -1, 0], [0, 0, -1]],
+            MountingMatrix::NegXYNegZ => [[-1, 0, 0], [0, 1, 0], [0, 0, -1]],
+            MountingMatrix::NegXNegYZ => [[-1, 0, 0], [0, -1, 0], [0, 0, 1]],
+            MountingMatrix::YXNegZ => [[0, 1, 0], [1, 0, 0], [0, 0, -1]],
+            MountingMatrix::YNegXZ => [[0, 1, 0], [-1, 0, 0], [0, 0, 1]],
+            MountingMatrix::NegYXZ => [[0, -1, 0], [1, 0, 0], [0, 0, 1]],
+            MountingMatrix::NegYNegXNegZ => [[0, -1, 0], [-1, 0, 0], [0, 0, -1]],
+        })
+    }
+}
+
+impl MountingOrientation {
+    pub const IDENTITY: Self = Self::from_matrix([[1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+
+    /// Every proper rotation that maps sensor axes onto body axes.
+    pub const ROTATIONS: [Self; 24] = {
+        let mut rotations = [Self::IDENTITY; 24];
+        let mut n = 0;
+        let mut i = 0;
+        while i < SignedAxis::ALL.len() {
+            let mut j = 0;
+            while j < SignedAxis::ALL.len() {
+                if let Some(rotation) = Self::from_axes(SignedAxis::ALL[i], SignedAxis::ALL[j]) {
+                    rotations[n] = rotation;
+                    n += 1;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        rotations
+    };
+
+    /// Use an arbitrary integer matrix, rows indexed by body axis.
+    pub const fn from_matrix(matrix: [[i8; 3]; 3]) -> Self {
+        Self { matrix }
+    }
+
+    /// The rotation under which the body X and Y axes point along the sensor axes `x` and `y`,
+    /// or `None` if they are not perpendicular.
+    pub const fn from_axes(x: SignedAxis, y: SignedAxis) -> Option<Self> {
+        let (x, y) = (x.unit(), y.unit());
+        // The body Z axis completes a right-handed frame.
+        let z = [
+            x[1] * y[2] - x[2] * y[1],
+            x[2] * y[0] - x[0] * y[2],
+            x[0] * y[1] - x[1] * y[0],
+        ];
+        if z[0] == 0 && z[1] == 0 && z[2] == 0 {
+            return None;
+        }
+        Some(Self::from_matrix([x, y, z]))
+    }
+
+    pub const fn matrix(&self) -> [[i8; 3]; 3] {
+        self.matrix
+    }
+
+    /// Whether the matrix is one of the 24 proper rotations.
+    pub fn is_rotation(&self) -> bool {
+        Self::ROTATIONS.contains(self)
+    }
+
+    /// The reverse transformation from body frame to sensor frame, if the orientation is a
+    /// rotation.
+    pub fn inverse(&self) -> Option<Self> {
+        if !self.is_rotation() {
+            return None;
+        }
+        let m = self.matrix;
+        Some(Self::from_matrix(
+            [0, 1, 2].map(|i| [m[0][i], m[1][i], m[2][i]]),
+        ))
+    }
+
+    /// The equivalent DMP mounting matrix, if the DMP supports this orientation.
+    pub fn mounting_matrix(&self) -> Option<MountingMatrix> {
+        MountingMatrix::ALL
+            .into_iter()
+            .find(|&matrix| Self::from(matrix) == *self)
+    }
+
+    /// Transform a raw sensor-frame vector into the body frame, saturating at the `i16` range.
+    pub fn apply_raw(&self, v: [i16; 3]) -> [i16; 3] {
+        self.matrix.map(|row| {
+            let sum: i32 = row
+                .iter()
+                .zip(v)
+                .map(|(&m, v)| i32::from(m) * i32::from(v))
+                .sum();
+            sum.clamp(i16::MIN.into(), i16::MAX.into()) as i16
+        })
+    }
+
+    /// Transform a sensor-frame vector into the body frame.
+    pub fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        self.matrix
+            .map(|row| row.iter().zip(v).map(|(&m, v)| f32::from(m) * v).sum())
+    }
+
+    /// Transform the accelerometer and gyroscope data of a FIFO packet into the body frame.
+    ///
+    /// A body axis is `None` if any sensor axis it depends on is `None`.
+    pub fn apply_packet(&self, packet: &FifoPacket) -> FifoPacket {
+        FifoPacket {
+            accel: self.apply_partial(packet.accel, |v| self.apply_raw(v)),
+            gyro: self.apply_partial(packet.gyro, |v| self.apply_raw(v)),
+            ..*packet
+        }
+    }
+
+    /// Transform the accelerometer and gyroscope data of a raw sample into the body frame.
+    ///
+    /// A body axis is `None` if any sensor axis it depends on is `None`.
+    pub fn apply_raw_sample(&self, sample: &RawSample) -> RawSample {
+        RawSample {
+            accel: self.apply_partial(sample.accel, |v| self.apply_raw(v)),
+            gyro: self.apply_partial(sample.gyro, |v| self.apply_raw(v)),
+            ..*sample
+        }
+    }
+
+    /// Transform a scaled sample into the body frame.
+    ///
+    /// A body axis is `None` if any sensor axis it depends on is `None`.
+    pub fn apply_sample(&self, sample: &ScaledSample) -> ScaledSample {
+        ScaledSample {
+            accel: self.apply_partial(sample.accel, |v| self.apply(v)),
+            gyro: self.apply_partial(sample.gyro, |v| self.apply(v)),
+            ..*sample
+        }
+    }
+
+    fn apply_partial<T: Copy + Default>(
+        &self,
+        v: [Option<T>; 3],
+        apply: impl Fn([T; 3]) -> [T; 3],
+    ) -> [Option<T>; 3] {
+        let body = apply(v.map(Option::unwrap_or_default));
+        let mut axis = 0;
+        self.matrix.map(|row| {
+            let valid = row.iter().zip(v).all(|(&m, v)| m == 0 || v.is_some());
+            let value = valid.then_some(body[axis]);
+            axis += 1;
+            value
+        })
+    }
+}
+
+/// Offsets the device applies to its output (`OFFSET_USER0` to `OFFSET_USER8`).
+///
+/// The gyroscope offsets have a resolution of 1/32 °/s and a range of ±64 °/s, the
+/// accelerometer offsets a resolution of 0.5 mg and a range of ±1 g.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct UserOffsets {
+    /// Accelerometer offsets in g.
+    pub accel: [f32; 3],
+    /// Gyroscope offsets in degrees per second.
+    pub gyro: [f32; 3],
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Read the user offsets, expressed in the body frame of `orientation`.
+    ///
+    /// Fails with [`Error::InvalidConfig`] if the orientation is not a rotation.
+    pub async fn read_user_offsets(
+        &mut self,
+        orientation: MountingOrientation,
+    ) -> Result<UserOffsets, Error<I::Error>> {
+        if !orientation.is_rotation() {
+            return Err(Error::InvalidConfig);
+        }
+        let mut raw = [0; 9];
+        let [bank, reg] = OFFSET_USER0.to_be_bytes();
+        self.read_block(bank, reg, &mut raw).await?;
+        let [gyro, accel] = unpack_offsets(raw);
+        let sensor = UserOffsets {
+            accel: accel.map(|v| f32::from(v) * ACCEL_OFFSET_G),
+            gyro: gyro.map(|v| f32::from(v) * GYRO_OFFSET_DPS),
+        };
+        Ok(UserOffsets {
+            accel: orientation.apply(sensor.accel),
+            gyro: orientation.apply(sensor.gyro),
+        })
+    }
+
+    /// Program user offsets given in the body frame of `orientation`.
+    ///
+    /// Fails with [`Error::InvalidConfig`] if the orientation is not a rotation or an offset is
+    /// out of range.
+    pub async fn write_user_offsets(
+        &mut self,
+        offsets: UserOffsets,
+        orientation: MountingOrientation,
+    ) -> Result<(), Error<I::Error>> {
+        let inverse = orientation.inverse().ok_or(Error::InvalidConfig)?;
+        let to_raw = |v: f32, resolution: f32| {
+            let raw = round(v / resolution);
+            (-2048.0..=2047.0)
+                .contains(&raw)
+                .then_some(raw as i16)
+                .ok_or(Error::InvalidConfig)
+        };
+        let mut gyro = [0; 3];
+        for (raw, v) in gyro.iter_mut().zip(inverse.apply(offsets.gyro)) {
+            *raw = to_raw(v, GYRO_OFFSET_DPS)?;
+        }
+        let mut accel = [0; 3];
+        for (raw, v) in accel.iter_mut().zip(inverse.apply(offsets.accel)) {
+            *raw = to_raw(v, ACCEL_OFFSET_G)?;
+        }
+
+        let [bank, reg] = OFFSET_USER0.to_be_bytes();
+        self.write_block(bank, reg, &pack_offsets([gyro, accel]))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Round half away from zero without relying on `std`.
+fn round(v: f32) -> f32 {
+    let truncated = v as i32 as f32;
+    if (v - truncated).abs() >= 0.5 {
+        truncated + v.signum()
+    } else {
+        truncated
+    }
+}
+
+/// Split `OFFSET_USER0` to `OFFSET_USER8` into the 12-bit gyroscope and accelerometer offsets.
+fn unpack_offsets(b: [u8; 9]) -> [[i16; 3]; 2] {
+    let value = |lo: u8, hi: u8| {
+        let raw = (u16::from(hi & 0x0f) << 8) | u16::from(lo);
+        // Sign-extend from 12 bits.
+        ((raw << 4) as i16) >> 4
+    };
+    [
+        [value(b[0], b[1]), value(b[2], b[1] >> 4), value(b[3], b[4])],
+        [
+            value(b[5], b[4] >> 4),
+            value(b[6], b[7]),
+            value(b[8], b[7] >> 4),
+        ],
+    ]
+}
+
+fn pack_offsets([gyro, accel]: [[i16; 3]; 2]) -> [u8; 9] {
+    let lo = |v: i16| v as u8;
+    let hi = |v: i16| ((v >> 8) as u8) & 0x0f;
+    [
+        lo(gyro[0]),
+        hi(gyro[1]) << 4 | hi(gyro[0]),
+        lo(gyro[1]),
+        lo(gyro[2]),
+        hi(accel[0]) << 4 | hi(gyro[2]),
+        lo(accel[0]),
+        lo(accel[1]),
+        hi(accel[2]) << 4 | hi(accel[1]),
+        lo(accel[2]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::RaiseToWake;
+    use crate::sim::{Simulator, Stationary};
+
+    #[test]
+    fn raise_to_wake_programs_the_mounting_orientation() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut delay = sim.delay();
+        let config = RaiseToWake {
+            orientation: MountingMatrix::NegXYNegZ.into(),
+            ..RaiseToWake::default()
+        };
+
+        block_on(device.enable_raise_to_wake(config, &mut delay)).unwrap();
+        let matrix = device.user_bank_4().apex_config_5().read().unwrap();
+        assert_eq!(matrix.mounting_matrix(), MountingMatrix::NegXYNegZ as u8);
+
+        let sideways = RaiseToWake {
+            orientation: MountingOrientation::from_axes(SignedAxis::Z, SignedAxis::X).unwrap(),
+            ..RaiseToWake::default()
+        };
+        assert_eq!(
+            block_on(device.enable_raise_to_wake(sideways, &mut delay)),
+            Err(Error::InvalidConfig)
+        );
+    }
+
+    #[test]
+    fn user_offsets_are_expressed_in_the_body_frame() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let orientation = MountingMatrix::YNegXZ.into();
+        let offsets = UserOffsets {
+            accel: [0.5, -0.25, 0.0],
+            gyro: [1.0, 2.0, -3.0],
+        };
+
+        block_on(device.write_user_offsets(offsets, orientation)).unwrap();
+        assert_eq!(block_on(device.read_user_offsets(orientation)), Ok(offsets));
+
+        let sensor = block_on(device.read_user_offsets(MountingOrientation::IDENTITY)).unwrap();
+        assert_eq!(sensor.accel, [0.25, 0.5, 0.0]);
+        assert_eq!(sensor.gyro, [-2.0, 1.0, -3.0]);
+        assert_eq!(
+            block_on(device.read_user_offsets(MountingOrientation::from_matrix([[1, 0, 0]; 3]))),
+            Err(Error::InvalidConfig)
+        );
+    }
+}
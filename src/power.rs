@@ -0,0 +1,422 @@
+//! Sensor power mode transitions.
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::{AccelLpClkSel, AccelMode, DataRate, Error, GyroMode, Icm42605};
+
+/// No register writes may be issued for this long after changing `PWR_MGMT0`.
+pub(crate) const MODE_CHANGE_US: u32 = 200;
+/// The gyroscope must be kept on for at least this long once enabled.
+const GYRO_MIN_ON_US: u32 = 45_000;
+/// Gyroscope start-up time from off or standby to valid data.
+const GYRO_STARTUP_US: u32 = 30_000;
+/// Accelerometer start-up time from off to valid data.
+const ACCEL_STARTUP_US: u32 = 10_000;
+
+/// Averaging applied to accelerometer samples in low-power mode (`ACCEL_UI_FILT_BW`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LowPowerAveraging {
+    X1,
+    #[default]
+    X16,
+}
+
+impl LowPowerAveraging {
+    /// The `ACCEL_UI_FILT_BW` encoding of this averaging in low-power mode.
+    const fn filt_bw(self) -> u8 {
+        match self {
+            Self::X1 => 1,
+            Self::X16 => 6,
+        }
+    }
+}
+
+/// Typical supply current in µA of the accelerometer in low-power mode, or `None` if `odr` is not
+/// supported with the given clock source (see [`DataRate::is_valid_for_accel_low_power`]).
+///
+/// The figures are the typical currents from the datasheet's table of accelerometer low-power mode
+/// current by ODR and averaging filter setting (1x and 16x AVG). The additional current drawn by
+/// the RC oscillator is not modelled, so the estimate for [`AccelLpClkSel::Rc`] is a lower bound.
+pub const fn accel_low_power_current_ua(
+    odr: DataRate,
+    clock: AccelLpClkSel,
+    averaging: LowPowerAveraging,
+) -> Option<u32> {
+    if !odr.is_valid_for_accel_low_power(clock) {
+        return None;
+    }
+    // (1x averaging, 16x averaging).
+    let (x1, x16) = match odr {
+        DataRate::Hz1X15625 => (6, 7),
+        DataRate::Hz3X125 => (6, 8),
+        DataRate::Hz6X25 => (7, 11),
+        DataRate::Hz12X5 => (8, 16),
+        DataRate::Hz25 => (10, 26),
+        DataRate::Hz50 => (14, 47),
+        DataRate::Hz100 => (22, 88),
+        DataRate::Hz200 => (39, 170),
+        DataRate::Hz500 => (89, 420),
+        _ => return None,
+    };
+    Some(match averaging {
+        LowPowerAveraging::X1 => x1,
+        LowPowerAveraging::X16 => x16,
+    })
+}
+
+/// Tracks the gyroscope and accelerometer power modes and enforces the settling rules of
+/// `PWR_MGMT0`.
+///
+/// Time is only known to the manager through the delays it performs itself and through
+/// [`PowerManager::elapsed`], so timing constraints are always met conservatively.
+#[derive(Debug)]
+pub struct PowerManager<D> {
+    delay: D,
+    gyro: GyroMode,
+    accel: AccelMode,
+    gyro_min_on_us: u32,
+    gyro_startup_us: u32,
+    accel_startup_us: u32,
+}
+
+impl<D: DelayNs> PowerManager<D> {
+    /// Create a manager for a device that has just been reset, with both sensors off.
+    pub fn new(delay: D) -> Self {
+        Self {
+            delay,
+            gyro: GyroMode::Off,
+            accel: AccelMode::Off,
+            gyro_min_on_us: 0,
+            gyro_startup_us: 0,
+            accel_startup_us: 0,
+        }
+    }
+
+    /// Create a manager from the modes currently programmed into the device.
+    ///
+    /// The sensors are assumed to have been running long enough for their data to be valid.
+    pub async fn sync<I>(delay: D, device: &mut Icm42605<I>) -> Result<Self, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let pwr = device.user_bank_0().pwr_mgmt().read_async().await?;
+        Ok(Self {
+            gyro: pwr.gyro_mode(),
+            accel: pwr.accel_mode(),
+            ..Self::new(delay)
+        })
+    }
+
+    pub fn gyro_mode(&self) -> GyroMode {
+        self.gyro
+    }
+
+    pub fn accel_mode(&self) -> AccelMode {
+        self.accel
+    }
+
+    /// Whether every enabled sensor has finished starting up and produces valid data.
+    pub fn data_valid(&self) -> bool {
+        self.gyro_startup_us == 0 && self.accel_startup_us == 0
+    }
+
+    /// Inform the manager that at least `us` microseconds have passed since the last call.
+    pub fn elapsed(&mut self, us: u32) {
+        self.gyro_min_on_us = self.gyro_min_on_us.saturating_sub(us);
+        self.gyro_startup_us = self.gyro_startup_us.saturating_sub(us);
+        self.accel_startup_us = self.accel_startup_us.saturating_sub(us);
+    }
+
+    /// Wait until [`PowerManager::data_valid`] holds.
+    pub async fn wait_data_valid(&mut self) {
+        let us = self.gyro_startup_us.max(self.accel_startup_us);
+        self.wait_us(us).await;
+    }
+
+    pub async fn set_gyro_mode<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        gyro: GyroMode,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        self.set_modes(device, gyro, self.accel).await
+    }
+
+    pub async fn set_accel_mode<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        accel: AccelMode,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        self.set_modes(device, self.gyro, accel).await
+    }
+
+    /// Switch both sensors to the given modes in a single `PWR_MGMT0` write.
+    ///
+    /// Reserved modes and accelerometer low-power mode with the gyroscope on are rejected with
+    /// [`Error::InvalidConfig`]. Turning the gyroscope off waits out its minimum on-time, and
+    /// every mode change is followed by the mandatory pause before further register writes.
+    pub async fn set_modes<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        gyro: GyroMode,
+        accel: AccelMode,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if gyro == GyroMode::Reserved || accel == AccelMode::Reserved {
+            return Err(Error::InvalidConfig);
+        }
+        // Accelerometer low-power mode is only available with the gyroscope off.
+        if accel == AccelMode::LowPower && gyro != GyroMode::Off {
+            return Err(Error::InvalidConfig);
+        }
+        if gyro == self.gyro && accel == self.accel {
+            return Ok(());
+        }
+
+        if self.gyro == GyroMode::LowNoise && gyro != GyroMode::LowNoise {
+            self.wait_us(self.gyro_min_on_us).await;
+        }
+
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .modify_async(|r| {
+                r.set_gyro_mode(gyro);
+                r.set_accel_mode(accel);
+            })
+            .await?;
+
+        self.gyro_startup_us = match (self.gyro, gyro) {
+            (GyroMode::LowNoise, GyroMode::LowNoise) => self.gyro_startup_us,
+            (_, GyroMode::LowNoise) => {
+                self.gyro_min_on_us = GYRO_MIN_ON_US;
+                GYRO_STARTUP_US
+            }
+            _ => 0,
+        };
+        self.accel_startup_us = match (self.accel, accel) {
+            (_, AccelMode::Off) => 0,
+            (AccelMode::Off, _) => ACCEL_STARTUP_US,
+            _ => self.accel_startup_us,
+        };
+        self.gyro = gyro;
+        self.accel = accel;
+
+        self.wait_us(MODE_CHANGE_US).await;
+        Ok(())
+    }
+
+    /// Run the accelerometer in low-power mode at `odr`, clocked from `clock`.
+    ///
+    /// The gyroscope must be off. Returns the typical supply current in µA, see
+    /// [`accel_low_power_current_ua`].
+    pub async fn enable_accel_low_power<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        odr: DataRate,
+        clock: AccelLpClkSel,
+        averaging: LowPowerAveraging,
+    ) -> Result<u32, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let current =
+            accel_low_power_current_ua(odr, clock, averaging).ok_or(Error::InvalidConfig)?;
+        if self.gyro != GyroMode::Off {
+            return Err(Error::InvalidConfig);
+        }
+
+        // The clock source and filter may only be changed while the accelerometer is off.
+        if self.accel != AccelMode::Off {
+            self.set_accel_mode(device, AccelMode::Off).await?;
+        }
+        device
+            .user_bank_0()
+            .intf_config_1()
+            .modify_async(|r| r.set_accel_lp_clk_sel(clock))
+            .await?;
+        device
+            .user_bank_0()
+            .accel_config_0()
+            .modify_async(|r| r.set_gyro_odr(odr))
+            .await?;
+        device
+            .user_bank_0()
+            .gyro_accel_config()
+            .modify_async(|r| r.set_accel_ui_filt_bw(averaging.filt_bw()))
+            .await?;
+        self.set_accel_mode(device, AccelMode::LowPower).await?;
+        Ok(current)
+    }
+
+    /// Return the accelerometer from low-power mode to low-noise mode at `odr`.
+    ///
+    /// The filter bandwidth is restored to its reset value and this waits until the
+    /// accelerometer produces valid data again.
+    pub async fn disable_accel_low_power<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+        odr: DataRate,
+    ) -> Result<(), Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        if !odr.is_valid_for_accel_low_noise() {
+            return Err(Error::InvalidConfig);
+        }
+
+        self.set_accel_mode(device, AccelMode::Off).await?;
+        device
+            .user_bank_0()
+            .accel_config_0()
+            .modify_async(|r| r.set_gyro_odr(odr))
+            .await?;
+        device
+            .user_bank_0()
+            .gyro_accel_config()
+            .modify_async(|r| r.set_accel_ui_filt_bw(1))
+            .await?;
+        self.set_accel_mode(device, AccelMode::LowNoise).await?;
+        self.wait_data_valid().await;
+        Ok(())
+    }
+
+    /// Release the delay provider.
+    pub fn free(self) -> D {
+        self.delay
+    }
+
+    async fn wait_us(&mut self, us: u32) {
+        if us > 0 {
+            self.delay.delay_us(us).await;
+            self.elapsed(us);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::sim::{SimBus, SimDelay, Simulator, Stationary};
+
+    fn modes(device: &mut Icm42605<SimBus<Stationary>>) -> (GyroMode, AccelMode) {
+        let pwr = device.user_bank_0().pwr_mgmt().read().unwrap();
+        (pwr.gyro_mode(), pwr.accel_mode())
+    }
+
+    #[test]
+    fn gyro_settles_and_stays_on_for_its_minimum_time() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut power = PowerManager::new(sim.delay());
+
+        block_on(power.set_gyro_mode(&mut device, GyroMode::LowNoise)).unwrap();
+        assert_eq!(modes(&mut device), (GyroMode::LowNoise, AccelMode::Off));
+        assert_eq!(sim.time_us(), u64::from(MODE_CHANGE_US));
+        assert!(!power.data_valid());
+
+        block_on(power.wait_data_valid());
+        assert!(power.data_valid());
+        assert_eq!(sim.time_us(), u64::from(GYRO_STARTUP_US));
+
+        // Turning the gyroscope off waits until it has been on for its minimum time.
+        block_on(power.set_gyro_mode(&mut device, GyroMode::Off)).unwrap();
+        assert_eq!(modes(&mut device), (GyroMode::Off, AccelMode::Off));
+        assert_eq!(sim.time_us(), u64::from(GYRO_MIN_ON_US + MODE_CHANGE_US));
+    }
+
+    #[test]
+    fn elapsed_time_counts_towards_the_settling_rules() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut power = PowerManager::new(sim.delay());
+
+        block_on(power.set_modes(&mut device, GyroMode::LowNoise, AccelMode::LowNoise)).unwrap();
+        sim.advance(u64::from(GYRO_MIN_ON_US));
+        power.elapsed(GYRO_MIN_ON_US);
+        assert!(power.data_valid());
+
+        let before = sim.time_us();
+        block_on(power.set_gyro_mode(&mut device, GyroMode::Off)).unwrap();
+        assert_eq!(sim.time_us() - before, u64::from(MODE_CHANGE_US));
+        assert_eq!(power.accel_mode(), AccelMode::LowNoise);
+    }
+
+    #[test]
+    fn invalid_mode_combinations_are_rejected() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut power = PowerManager::new(sim.delay());
+
+        block_on(power.set_gyro_mode(&mut device, GyroMode::LowNoise)).unwrap();
+        assert_eq!(
+            block_on(power.set_accel_mode(&mut device, AccelMode::LowPower)),
+            Err(Error::InvalidConfig)
+        );
+        assert_eq!(
+            block_on(power.set_gyro_mode(&mut device, GyroMode::Reserved)),
+            Err(Error::InvalidConfig)
+        );
+        assert_eq!(modes(&mut device), (GyroMode::LowNoise, AccelMode::Off));
+    }
+
+    #[test]
+    fn sync_reads_the_programmed_modes() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| r.set_accel_mode(AccelMode::LowNoise))
+            .unwrap();
+
+        let power: PowerManager<SimDelay<Stationary>> =
+            block_on(PowerManager::sync(sim.delay(), &mut device)).unwrap();
+        assert_eq!(power.gyro_mode(), GyroMode::Off);
+        assert_eq!(power.accel_mode(), AccelMode::LowNoise);
+        assert!(power.data_valid());
+    }
+
+    fn accel_ui_filt_bw(device: &mut Icm42605<SimBus<Stationary>>) -> u8 {
+        let config = device.user_bank_0().gyro_accel_config().read().unwrap();
+        config.accel_ui_filt_bw()
+    }
+
+    #[test]
+    fn low_power_averaging_uses_the_low_power_encoding() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut power = PowerManager::new(sim.delay());
+
+        for (averaging, filt_bw) in [(LowPowerAveraging::X1, 1), (LowPowerAveraging::X16, 6)] {
+            let current = block_on(power.enable_accel_low_power(
+                &mut device,
+                DataRate::Hz50,
+                AccelLpClkSel::WakeUp,
+                averaging,
+            ))
+            .unwrap();
+            assert_eq!(
+                Some(current),
+                accel_low_power_current_ua(DataRate::Hz50, AccelLpClkSel::WakeUp, averaging)
+            );
+            assert_eq!(accel_ui_filt_bw(&mut device), filt_bw);
+            assert_eq!(modes(&mut device), (GyroMode::Off, AccelMode::LowPower));
+        }
+
+        block_on(power.disable_accel_low_power(&mut device, DataRate::Hz200)).unwrap();
+        assert_eq!(accel_ui_filt_bw(&mut device), 1);
+        assert_eq!(modes(&mut device), (GyroMode::Off, AccelMode::LowNoise));
+    }
+}
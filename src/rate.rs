@@ -0,0 +1,225 @@
+//! Output data rates.
+
+use core::time::Duration;
+
+use crate::{AccelLpClkSel, DataRate, DmpDataRate};
+
+/// Largest relative difference at which a frequency still matches a rate exactly, to absorb
+/// rounding in frequencies computed from periods.
+const EXACT_TOLERANCE: f32 = 1e-6;
+
+impl DataRate {
+    /// All valid rates, fastest first.
+    pub const ALL: [Self; 13] = [
+        Self::Hz8000,
+        Self::Hz4000,
+        Self::Hz2000,
+        Self::Hz1000,
+        Self::Hz500,
+        Self::Hz200,
+        Self::Hz100,
+        Self::Hz50,
+        Self::Hz25,
+        Self::Hz12X5,
+        Self::Hz6X25,
+        Self::Hz3X125,
+        Self::Hz1X15625,
+    ];
+
+    /// Sample period in nanoseconds, which is exact for every rate.
+    const fn period_ns(self) -> Option<u32> {
+        Some(match self {
+            Self::Hz8000 => 125_000,
+            Self::Hz4000 => 250_000,
+            Self::Hz2000 => 500_000,
+            Self::Hz1000 => 1_000_000,
+            Self::Hz500 => 2_000_000,
+            Self::Hz200 => 5_000_000,
+            Self::Hz100 => 10_000_000,
+            Self::Hz50 => 20_000_000,
+            Self::Hz25 => 40_000_000,
+            Self::Hz12X5 => 80_000_000,
+            Self::Hz6X25 => 160_000_000,
+            Self::Hz3X125 => 320_000_000,
+            Self::Hz1X15625 => 640_000_000,
+            Self::Reserved(_) => return None,
+        })
+    }
+
+    /// Frequency in Hz, or `None` for reserved values.
+    pub const fn hz(self) -> Option<f32> {
+        match self.period_ns() {
+            Some(ns) => Some(1e9 / ns as f32),
+            None => None,
+        }
+    }
+
+    /// Time between samples, or `None` for reserved values.
+    pub const fn period(self) -> Option<Duration> {
+        match self.period_ns() {
+            Some(ns) => Some(Duration::from_nanos(ns as u64)),
+            None => None,
+        }
+    }
+
+    /// The rate of `hz`, if there is one, allowing for rounding errors.
+    pub fn from_hz_exact(hz: f32) -> Option<Self> {
+        exact(&Self::ALL, hz, |rate| rate.hz())
+    }
+
+    /// The rate closest to `hz` by ratio, so 9 Hz maps to 12.5 Hz rather than 6.25 Hz, or `None`
+    /// if `hz` is not a positive, finite frequency.
+    ///
+    /// Rates not supported by the intended sensor and mode are considered as well; check the
+    /// result with [`DataRate::is_valid_for_gyro`] and related functions.
+    pub fn from_hz_nearest(hz: f32) -> Option<Self> {
+        nearest(&Self::ALL, hz, |rate| rate.hz())
+    }
+
+    /// Whether the gyroscope supports this rate (12.5 Hz to 8 kHz).
+    pub const fn is_valid_for_gyro(self) -> bool {
+        matches!(
+            self,
+            Self::Hz8000
+                | Self::Hz4000
+                | Self::Hz2000
+                | Self::Hz1000
+                | Self::Hz500
+                | Self::Hz200
+                | Self::Hz100
+                | Self::Hz50
+                | Self::Hz25
+                | Self::Hz12X5
+        )
+    }
+
+    /// Whether the accelerometer supports this rate in low-noise mode (12.5 Hz to 8 kHz).
+    pub const fn is_valid_for_accel_low_noise(self) -> bool {
+        self.is_valid_for_gyro()
+    }
+
+    /// Whether the accelerometer supports this rate in low-power mode clocked from `clock`.
+    ///
+    /// The wake-up oscillator supports 1.5625 Hz to 200 Hz, the RC oscillator 1.5625 Hz to
+    /// 500 Hz.
+    pub const fn is_valid_for_accel_low_power(self, clock: AccelLpClkSel) -> bool {
+        match self {
+            Self::Hz200
+            | Self::Hz100
+            | Self::Hz50
+            | Self::Hz25
+            | Self::Hz12X5
+            | Self::Hz6X25
+            | Self::Hz3X125
+            | Self::Hz1X15625 => true,
+            Self::Hz500 => matches!(clock, AccelLpClkSel::Rc),
+            _ => false,
+        }
+    }
+}
+
+impl DmpDataRate {
+    /// All valid rates, fastest first.
+    pub const ALL: [Self; 2] = [Self::Hz50, Self::Hz25];
+
+    const fn period_ms(self) -> Option<u32> {
+        match self {
+            Self::Hz25 => Some(40),
+            Self::Hz50 => Some(20),
+            Self::Reserved(_) => None,
+        }
+    }
+
+    /// Frequency in Hz, or `None` for reserved values.
+    pub const fn hz(self) -> Option<f32> {
+        match self.period_ms() {
+            Some(ms) => Some(1e3 / ms as f32),
+            None => None,
+        }
+    }
+
+    /// Time between DMP samples, or `None` for reserved values.
+    pub const fn period(self) -> Option<Duration> {
+        match self.period_ms() {
+            Some(ms) => Some(Duration::from_millis(ms as u64)),
+            None => None,
+        }
+    }
+
+    /// The rate of `hz`, if there is one, allowing for rounding errors.
+    pub fn from_hz_exact(hz: f32) -> Option<Self> {
+        exact(&Self::ALL, hz, |rate| rate.hz())
+    }
+
+    /// The rate closest to `hz` by ratio, or `None` if `hz` is not a positive, finite frequency.
+    pub fn from_hz_nearest(hz: f32) -> Option<Self> {
+        nearest(&Self::ALL, hz, |rate| rate.hz())
+    }
+
+    /// Whether the DMP can run at this rate with sensors running at `odr`, which must not be
+    /// slower.
+    pub const fn is_valid_with_odr(self, odr: DataRate) -> bool {
+        match (self.period_ms(), odr.period_ns()) {
+            (Some(dmp), Some(accel)) => accel <= dmp * 1_000_000,
+            _ => false,
+        }
+    }
+}
+
+/// The entry of `rates` whose frequency is within [`EXACT_TOLERANCE`] of `hz`.
+fn exact<T: Copy>(rates: &[T], hz: f32, rate_hz: impl Fn(T) -> Option<f32>) -> Option<T> {
+    let matches = |rate: f32| (rate - hz).abs() <= rate * EXACT_TOLERANCE;
+    rates
+        .iter()
+        .copied()
+        .find(|&rate| rate_hz(rate).is_some_and(matches))
+}
+
+/// The entry of `rates` whose frequency is closest to `hz` by ratio.
+fn nearest<T: Copy>(rates: &[T], hz: f32, rate_hz: impl Fn(T) -> Option<f32>) -> Option<T> {
+    if !(hz.is_finite() && hz > 0.0) {
+        return None;
+    }
+    let distance = |rate: T| {
+        let rate = rate_hz(rate).unwrap_or(f32::NAN);
+        if rate > hz { rate / hz } else { hz / rate }
+    };
+    let mut best = rates[0];
+    for &rate in rates {
+        if distance(rate) < distance(best) {
+            best = rate;
+        }
+    }
+    Some(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_rates_tolerate_rounding() {
+        assert_eq!(
+            DataRate::from_hz_exact(1.0 / 0.64),
+            Some(DataRate::Hz1X15625)
+        );
+        assert_eq!(DataRate::from_hz_exact(1.0 / 0.08), Some(DataRate::Hz12X5));
+        assert_eq!(DataRate::from_hz_exact(1000.0005), Some(DataRate::Hz1000));
+        assert_eq!(DataRate::from_hz_exact(1000.1), None);
+        assert_eq!(
+            DmpDataRate::from_hz_exact(1.0 / 0.02),
+            Some(DmpDataRate::Hz50)
+        );
+    }
+
+    #[test]
+    fn nearest_rates_need_a_positive_frequency() {
+        assert_eq!(DataRate::from_hz_nearest(9.0), Some(DataRate::Hz12X5));
+        assert_eq!(DataRate::from_hz_nearest(1e9), Some(DataRate::Hz8000));
+        assert_eq!(DmpDataRate::from_hz_nearest(30.0), Some(DmpDataRate::Hz25));
+        for hz in [0.0, -50.0, f32::NAN, f32::INFINITY] {
+            assert_eq!(DataRate::from_hz_nearest(hz), None);
+            assert_eq!(DmpDataRate::from_hz_nearest(hz), None);
+        }
+    }
+}
@@ -0,0 +1,526 @@
+//! Recording of register accesses and replay of recorded traces.
+
+use core::fmt;
+
+use device_driver::{AsyncRegisterInterface, RegisterInterface};
+
+use crate::{BankCache, SerialInterface, SerialInterfaceKind};
+
+/// Length of the encoded record header that precedes the data bytes.
+const HEADER_LEN: usize = 9;
+
+/// Kind of a recorded register access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum RecordKind {
+    Read,
+    Write,
+    /// A read that failed with a bus error. The record carries no data.
+    ReadFailed,
+    /// A write that failed with a bus error. The record carries the data that was written.
+    WriteFailed,
+}
+
+impl RecordKind {
+    const fn from_bits(bits: u8) -> Option<Self> {
+        Some(match bits {
+            0 => Self::Read,
+            1 => Self::Write,
+            2 => Self::ReadFailed,
+            3 => Self::WriteFailed,
+            _ => return None,
+        })
+    }
+
+    const fn is_read(self) -> bool {
+        matches!(self, Self::Read | Self::ReadFailed)
+    }
+}
+
+/// A single recorded register access.
+///
+/// The binary encoding is a 9-byte header — kind, bank, register, data length (`u16`,
+/// little-endian) and timestamp (`u32`, little-endian) — followed by the data bytes. The
+/// [`Display`](fmt::Display) implementation produces one human-readable line per record, e.g.
+/// `R 00:2d @1000 08`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Record<'a> {
+    pub kind: RecordKind,
+    /// Register address with the bank in the upper byte.
+    pub address: u16,
+    /// Time of the access in microseconds, as reported by the recorder's clock.
+    pub timestamp_us: u32,
+    pub data: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    pub const fn bank(&self) -> u8 {
+        self.address.to_be_bytes()[0]
+    }
+
+    pub const fn register(&self) -> u8 {
+        self.address.to_be_bytes()[1]
+    }
+
+    /// Length of the binary encoding in bytes.
+    pub const fn encoded_len(&self) -> usize {
+        HEADER_LEN + self.data.len()
+    }
+
+    /// Encode the record into the start of `buf`, returning the number of bytes written or
+    /// `None` if `buf` is too short.
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let len = u16::try_from(self.data.len()).ok()?;
+        let buf = buf.get_mut(..self.encoded_len())?;
+        let (header, data) = buf.split_at_mut(HEADER_LEN);
+        let [bank, reg] = self.address.to_be_bytes();
+        header[..3].copy_from_slice(&[self.kind as u8, bank, reg]);
+        header[3..5].copy_from_slice(&len.to_le_bytes());
+        header[5..].copy_from_slice(&self.timestamp_us.to_le_bytes());
+        data.copy_from_slice(self.data);
+        Some(self.encoded_len())
+    }
+
+    /// Decode the record at the start of `bytes`, returning it and the remaining bytes.
+    pub fn decode(bytes: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        let (header, rest) = bytes.split_at_checked(HEADER_LEN)?;
+        let kind = RecordKind::from_bits(header[0])?;
+        let len = usize::from(u16::from_le_bytes([header[3], header[4]]));
+        let (data, rest) = rest.split_at_checked(len)?;
+        let record = Self {
+            kind,
+            address: u16::from_be_bytes([header[1], header[2]]),
+            timestamp_us: u32::from_le_bytes([header[5], header[6], header[7], header[8]]),
+            data,
+        };
+        Some((record, rest))
+    }
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            RecordKind::Read => "R",
+            RecordKind::Write => "W",
+            RecordKind::ReadFailed => "R!",
+            RecordKind::WriteFailed => "W!",
+        };
+        write!(
+            f,
+            "{kind} {:02x}:{:02x} @{}",
+            self.bank(),
+            self.register(),
+            self.timestamp_us
+        )?;
+        for byte in self.data {
+            write!(f, " {byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Destination of the records produced by a [`Recorder`].
+pub trait RecordSink {
+    fn record(&mut self, record: &Record<'_>);
+}
+
+impl<F: FnMut(&Record<'_>)> RecordSink for F {
+    fn record(&mut self, record: &Record<'_>) {
+        self(record)
+    }
+}
+
+/// Appends binary records until the buffer is full; records that no longer fit, or that carry
+/// more data than [`Record::encode`] supports, are dropped.
+impl<const N: usize> RecordSink for heapless::Vec<u8, N> {
+    fn record(&mut self, record: &Record<'_>) {
+        let start = self.len();
+        if self.resize(start + record.encoded_len(), 0).is_err()
+            || record.encode(&mut self[start..]).is_none()
+        {
+            self.truncate(start);
+        }
+    }
+}
+
+/// Appends binary records; records that carry more data than [`Record::encode`] supports are
+/// dropped.
+#[cfg(any(test, feature = "mock"))]
+impl RecordSink for std::vec::Vec<u8> {
+    fn record(&mut self, record: &Record<'_>) {
+        let start = self.len();
+        self.resize(start + record.encoded_len(), 0);
+        if record.encode(&mut self[start..]).is_none() {
+            self.truncate(start);
+        }
+    }
+}
+
+/// A register interface wrapper that records every access made through it.
+///
+/// `clock` returns the current time in microseconds and is sampled once per access.
+#[derive(Debug)]
+pub struct Recorder<I, C, S> {
+    interface: I,
+    clock: C,
+    sink: S,
+}
+
+impl<I, C: FnMut() -> u32, S: RecordSink> Recorder<I, C, S> {
+    pub fn new(interface: I, clock: C, sink: S) -> Self {
+        Self {
+            interface,
+            clock,
+            sink,
+        }
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    /// Release the wrapped interface, the clock and the sink.
+    pub fn free(self) -> (I, C, S) {
+        (self.interface, self.clock, self.sink)
+    }
+
+    fn record<E>(&mut self, address: u16, data: &[u8], result: &Result<(), E>, write: bool) {
+        let kind = match (write, result.is_ok()) {
+            (false, true) => RecordKind::Read,
+            (true, true) => RecordKind::Write,
+            (false, false) => RecordKind::ReadFailed,
+            (true, false) => RecordKind::WriteFailed,
+        };
+        let record = Record {
+            kind,
+            address,
+            timestamp_us: (self.clock)(),
+            data: if kind == RecordKind::ReadFailed {
+                &[]
+            } else {
+                data
+            },
+        };
+        self.sink.record(&record);
+    }
+}
+
+impl<I, C, S> RegisterInterface for Recorder<I, C, S>
+where
+    I: RegisterInterface<AddressType = u16>,
+    C: FnMut() -> u32,
+    S: RecordSink,
+{
+    type Error = I::Error;
+
+    type AddressType = u16;
+
+    fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.interface.write_register(address, size_bits, data);
+        self.record(address, data, &result, true);
+        result
+    }
+
+    fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.interface.read_register(address, size_bits, data);
+        self.record(address, data, &result, false);
+        result
+    }
+}
+
+impl<I, C, S> AsyncRegisterInterface for Recorder<I, C, S>
+where
+    I: AsyncRegisterInterface<AddressType = u16>,
+    C: FnMut() -> u32,
+    S: RecordSink,
+{
+    type Error = I::Error;
+
+    type AddressType = u16;
+
+    async fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        let result = self
+            .interface
+            .write_register(address, size_bits, data)
+            .await;
+        self.record(address, data, &result, true);
+        result
+    }
+
+    async fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let result = self.interface.read_register(address, size_bits, data).await;
+        self.record(address, data, &result, false);
+        result
+    }
+}
+
+impl<I: SerialInterfaceKind, C, S> SerialInterfaceKind for Recorder<I, C, S> {
+    const KIND: SerialInterface = I::KIND;
+}
+
+impl<I: BankCache, C, S> BankCache for Recorder<I, C, S> {
+    fn invalidate_bank(&mut self) {
+        self.interface.invalidate_bank();
+    }
+}
+
+/// Error returned by [`Replay`] when the driver deviates from the recorded trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReplayError {
+    /// The driver made more accesses than were recorded.
+    Exhausted,
+    /// The record at `index` could not be decoded.
+    Corrupt { index: usize },
+    /// Access `index` differs from the recorded one in kind, address, length or written data.
+    Mismatch { index: usize, address: u16 },
+    /// The recorded access failed with a bus error.
+    Bus { index: usize, address: u16 },
+}
+
+/// A register interface that serves the responses of a binary trace recorded by a [`Recorder`].
+///
+/// Every access must match the next record; reads return the recorded data and writes are
+/// compared against it. Timestamps are not used for pacing.
+#[derive(Debug, Clone)]
+pub struct Replay<'a> {
+    log: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Replay<'a> {
+    pub const fn new(log: &'a [u8]) -> Self {
+        Self { log, index: 0 }
+    }
+
+    /// Number of records consumed so far.
+    pub const fn position(&self) -> usize {
+        self.index
+    }
+
+    /// Whether every recorded access has been replayed.
+    pub const fn is_finished(&self) -> bool {
+        self.log.is_empty()
+    }
+
+    /// The next record to be replayed, without consuming it.
+    pub fn peek(&self) -> Option<Record<'a>> {
+        Record::decode(self.log).map(|(record, _)| record)
+    }
+
+    /// Consume the next record if it matches an access to `address` of `len` bytes, which is a
+    /// write of `written` if given and a read otherwise.
+    fn next(
+        &mut self,
+        address: u16,
+        len: usize,
+        written: Option<&[u8]>,
+    ) -> Result<Record<'a>, ReplayError> {
+        let index = self.index;
+        if self.log.is_empty() {
+            return Err(ReplayError::Exhausted);
+        }
+        let (record, rest) = Record::decode(self.log).ok_or(ReplayError::Corrupt { index })?;
+        let failed = matches!(
+            record.kind,
+            RecordKind::ReadFailed | RecordKind::WriteFailed
+        );
+        if record.kind.is_read() != written.is_none()
+            || record.address != address
+            || (record.data.len() != len && record.kind != RecordKind::ReadFailed)
+            || written.is_some_and(|data| data != record.data)
+        {
+            return Err(ReplayError::Mismatch { index, address });
+        }
+        self.log = rest;
+        self.index += 1;
+        if failed {
+            return Err(ReplayError::Bus { index, address });
+        }
+        Ok(record)
+    }
+}
+
+impl RegisterInterface for Replay<'_> {
+    type Error = ReplayError;
+
+    type AddressType = u16;
+
+    fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        self.next(address, data.len(), Some(data)).map(|_| ())
+    }
+
+    fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        _size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        let record = self.next(address, data.len(), None)?;
+        data.copy_from_slice(record.data);
+        Ok(())
+    }
+}
+
+impl AsyncRegisterInterface for Replay<'_> {
+    type Error = ReplayError;
+
+    type AddressType = u16;
+
+    async fn write_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        RegisterInterface::write_register(self, address, size_bits, data)
+    }
+
+    async fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        RegisterInterface::read_register(self, address, size_bits, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use device_driver::RegisterInterface;
+
+    use super::{Record, RecordKind, RecordSink, Recorder, Replay, ReplayError};
+    use crate::mock::{MockDevice, MockError};
+
+    /// Record a write to `INT_CONFIG`, a read of `WHO_AM_I` and a failed read.
+    fn trace() -> Vec<u8> {
+        let mut time = 0;
+        let clock = move || {
+            time += 10;
+            time
+        };
+        let mut recorder = Recorder::new(MockDevice::new(false), clock, Vec::new());
+        recorder.write_register(0x0014, 8, &[0x03]).unwrap();
+        let mut who_am_i = [0];
+        recorder.read_register(0x0075, 8, &mut who_am_i).unwrap();
+        assert_eq!(
+            recorder.read_register(0x004b, 8, &mut [0]),
+            Err(MockError::WriteOnly(0x004b))
+        );
+        recorder.free().2
+    }
+
+    #[test]
+    fn records_decode_in_order() {
+        let log = trace();
+        let (first, rest) = Record::decode(&log).unwrap();
+        assert_eq!(
+            first,
+            Record {
+                kind: RecordKind::Write,
+                address: 0x0014,
+                timestamp_us: 10,
+                data: &[0x03],
+            }
+        );
+        let (second, rest) = Record::decode(rest).unwrap();
+        assert_eq!((second.kind, second.data), (RecordKind::Read, &[0x42][..]));
+        let (third, rest) = Record::decode(rest).unwrap();
+        assert_eq!((third.kind, third.data), (RecordKind::ReadFailed, &[][..]));
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn replays_recorded_accesses() {
+        let log = trace();
+        let mut replay = Replay::new(&log);
+
+        replay.write_register(0x0014, 8, &[0x03]).unwrap();
+        let mut who_am_i = [0];
+        replay.read_register(0x0075, 8, &mut who_am_i).unwrap();
+        assert_eq!(who_am_i, [0x42]);
+        assert_eq!(
+            replay.read_register(0x004b, 8, &mut [0]),
+            Err(ReplayError::Bus {
+                index: 2,
+                address: 0x004b
+            })
+        );
+        assert!(replay.is_finished());
+        assert_eq!(
+            replay.read_register(0x0075, 8, &mut who_am_i),
+            Err(ReplayError::Exhausted)
+        );
+    }
+
+    #[test]
+    fn mismatches_leave_the_record_in_place() {
+        let log = trace();
+        let mut replay = Replay::new(&log);
+        let mismatch = Err(ReplayError::Mismatch {
+            index: 0,
+            address: 0x0014,
+        });
+
+        assert_eq!(replay.write_register(0x0014, 8, &[0x01]), mismatch);
+        assert_eq!(replay.position(), 0);
+        assert_eq!(replay.read_register(0x0014, 8, &mut [0]), mismatch);
+        assert_eq!(replay.position(), 0);
+        replay.write_register(0x0014, 8, &[0x03]).unwrap();
+        assert_eq!(replay.position(), 1);
+    }
+
+    #[test]
+    fn oversized_records_are_dropped_whole() {
+        let data = std::vec![0xa5; usize::from(u16::MAX) + 1];
+        let oversized = Record {
+            kind: RecordKind::Read,
+            address: 0x0030,
+            timestamp_us: 20,
+            data: &data,
+        };
+
+        let mut log = trace();
+        let len = log.len();
+        log.record(&oversized);
+        assert_eq!(log.len(), len);
+
+        let mut buf = heapless::Vec::<u8, { 1 << 17 }>::new();
+        buf.record(&oversized);
+        assert!(buf.is_empty());
+    }
+}
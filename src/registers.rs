@@ -0,0 +1,356 @@
+//! Static description of every register in the device.
+
+use core::fmt;
+
+use device_driver::FieldSet;
+
+use crate::field_sets;
+
+/// How a register may be accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegisterAccess {
+    ReadWrite,
+    ReadOnly,
+    /// Read-only, and reading has side effects such as clearing status flags or consuming FIFO
+    /// data.
+    ReadClear,
+    WriteOnly,
+}
+
+impl RegisterAccess {
+    pub const fn is_readable(self) -> bool {
+        !matches!(self, Self::WriteOnly)
+    }
+
+    pub const fn is_writable(self) -> bool {
+        matches!(self, Self::ReadWrite | Self::WriteOnly)
+    }
+}
+
+/// A register of the device, as described in the register DSL.
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterInfo {
+    pub name: &'static str,
+    /// Register address, with the user bank in the upper byte.
+    pub address: u16,
+    pub size_bytes: usize,
+    pub access: RegisterAccess,
+    pub(crate) fields: &'static [FieldInfo],
+    reset_value: fn(&mut [u8]),
+    decode: fn(&[u8]) -> field_sets::FieldSetValue,
+}
+
+impl RegisterInfo {
+    pub const fn bank(&self) -> u8 {
+        self.address.to_be_bytes()[0]
+    }
+
+    /// Write the `size_bytes` bytes of the reset value into the start of `buf`.
+    pub fn reset_value(&self, buf: &mut [u8]) {
+        (self.reset_value)(&mut buf[..self.size_bytes]);
+    }
+
+    /// Decode the `size_bytes` bytes at the start of `value` into the register's field set.
+    pub fn decode(&self, value: &[u8]) -> field_sets::FieldSetValue {
+        (self.decode)(&value[..self.size_bytes])
+    }
+
+    /// Look up the register containing the byte at `address`.
+    pub fn containing(address: u16) -> Option<&'static Self> {
+        REGISTERS
+            .iter()
+            .find(|reg| (reg.address..reg.address + reg.size_bytes as u16).contains(&address))
+    }
+}
+
+/// A field of a register, read through the accessor of the generated field set.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FieldInfo {
+    pub name: &'static str,
+    /// Whether the field differs between two values of the register.
+    pub changed: fn(&[u8], &[u8]) -> bool,
+    /// Format the field of a register value with its `Debug` implementation.
+    pub fmt: fn(&[u8], &mut fmt::Formatter<'_>) -> fmt::Result,
+}
+
+fn field_set<F: FieldSet>(bytes: &[u8]) -> F {
+    let mut field_set = F::new_with_zero();
+    field_set.get_inner_buffer_mut().copy_from_slice(bytes);
+    field_set
+}
+
+macro_rules! registers {
+    ($(
+        $name:ident: $field_set:ident @ $address:literal, $access:ident { $($field:ident),* };
+    )*) => {
+        &[$(
+            RegisterInfo {
+                name: stringify!($name),
+                address: $address,
+                size_bytes: (<field_sets::$field_set as FieldSet>::SIZE_BITS / 8) as usize,
+                access: RegisterAccess::$access,
+                fields: &[$(
+                    FieldInfo {
+                        name: stringify!($field),
+                        changed: |old, new| {
+                            field_set::<field_sets::$field_set>(old).$field()
+                                != field_set::<field_sets::$field_set>(new).$field()
+                        },
+                        fmt: |value, f| {
+                            write!(f, "{:?}", field_set::<field_sets::$field_set>(value).$field())
+                        },
+                    },
+                )*],
+                reset_value: |buf| buf.copy_from_slice(field_sets::$field_set::new().get_inner_buffer()),
+                decode: |value| field_set::<field_sets::$field_set>(value).into(),
+            },
+        )*]
+    };
+}
+
+/// Every register of the device, in address order.
+pub const REGISTERS: &[RegisterInfo] = registers! {
+    DeviceConfig: DeviceConfig @ 0x0011, ReadWrite { spi_mode, soft_reset_config };
+    DriveConfig: DriveConfig @ 0x0013, ReadWrite { i_2_c_slew_rate, spi_slew_rate };
+    IntConfig: IntConfig @ 0x0014, ReadWrite {
+        int_2_mode, int_2_drive_circuit, int_2_polarity, int_1_mode, int_1_drive_circuit,
+        int_1_polarity
+    };
+    FifoConfig: FifoConfig @ 0x0016, ReadWrite { fifo_mode };
+    TempData: TempData @ 0x001d, ReadOnly { value };
+    AccelDataX: TempData @ 0x001f, ReadOnly { value };
+    AccelDataY: TempData @ 0x0021, ReadOnly { value };
+    AccelDataZ: TempData @ 0x0023, ReadOnly { value };
+    GyroDataX: TempData @ 0x0025, ReadOnly { value };
+    GyroDataY: TempData @ 0x0027, ReadOnly { value };
+    GyroDataZ: TempData @ 0x0029, ReadOnly { value };
+    TimestampFsync: TimestampFsync @ 0x002b, ReadOnly { value };
+    IntStatus: IntStatus @ 0x002d, ReadClear {
+        ui_fsync_int, pll_rdy_int, reset_done_int, data_rdy_int, fifo_ths_int, fifo_full_int,
+        agc_rdy_int
+    };
+    FifoCount: FifoCount @ 0x002e, ReadOnly { value };
+    FifoData: FifoData @ 0x0030, ReadClear { value };
+    StepCount: StepCount @ 0x0031, ReadOnly { value };
+    StepCadence: StepCadence @ 0x0033, ReadOnly { value };
+    ApexStatus: ApexStatus @ 0x0034, ReadOnly { dmp_idle, activity_class };
+    TapStatus: TapStatus @ 0x0035, ReadOnly { tap_num, tap_axis, tap_dir };
+    DoubleTapTiming: DoubleTapTiming @ 0x0036, ReadOnly { value };
+    IntStatus2: IntStatus2 @ 0x0037, ReadClear { smd_int, wom_z_int, wom_y_int, wom_x_int };
+    IntStatus3: IntStatus3 @ 0x0038, ReadClear {
+        step_det_int, step_cnt_ovf_int, tilt_det_int, wake_int, sleep_int, tap_det_int
+    };
+    SignalPathReset: SignalPathReset @ 0x004b, WriteOnly {
+        dmp_init_en, dmp_mem_reset_en, abort_and_reset, tmst_strobe, fifo_flush
+    };
+    IntfConfig0: IntfConfig0 @ 0x004c, ReadWrite {
+        fifo_hold_last_data_en, fifo_count_rec, fifo_count_endian, sensor_data_endian, ui_sifs_cfg
+    };
+    IntfConfig1: IntfConfig1 @ 0x004d, ReadWrite { accel_lp_clk_sel, clksel };
+    PwrMgmt: PwrMgmt @ 0x004e, ReadWrite { temp_dis, idle, gyro_mode, accel_mode };
+    GyroConfig0: GyroConfig0 @ 0x004f, ReadWrite { gyro_fs_sel, gyro_odr };
+    AccelConfig0: AccelConfig0 @ 0x0050, ReadWrite { gyro_fs_sel, gyro_odr };
+    GyroConfig1: GyroConfig1 @ 0x0051, ReadWrite {
+        temp_filt_bw, gyro_ui_filt_ord, gyro_dec_2_m_2_ord
+    };
+    GyroAccelConfig: GyroAccelConfig @ 0x0052, ReadWrite { accel_ui_filt_bw, gyro_ui_filt_bw };
+    AccelConfig1: AccelConfig1 @ 0x0053, ReadWrite { accel_ui_filt_ord, accel_dec_2_m_2_ord };
+    TmstConfig: TmstConfig @ 0x0054, ReadWrite {
+        tmst_to_regs_en, tmst_res, tmst_delta_en, tmst_fsync_en, tmst_en
+    };
+    ApexConfig: ApexConfig @ 0x0056, ReadWrite {
+        dmp_power_save, tap_enable, ped_enable, tilt_enable, r_2_w_en, dmp_odr
+    };
+    SmdConfig: SmdConfig @ 0x0057, ReadWrite { wom_int_mode, wom_mode, smd_mode };
+    FifoConfig1: FifoConfig1 @ 0x005f, ReadWrite {
+        fifo_resume_partial_rd, fifo_wm_gt_th, fifo_tmst_fsync_en, fifo_temp_en, fifo_gyro_en,
+        fifo_accel_en
+    };
+    FifoWatermark: FifoWatermark @ 0x0060, ReadWrite { value };
+    FsyncConfig: FsyncConfig @ 0x0062, ReadWrite {
+        fsync_ui_sel, fsync_ui_flag_clear_sel, fsync_polarity
+    };
+    IntConfig0: IntConfig0 @ 0x0063, ReadWrite {
+        ui_drdy_int_clear, fifo_ths_int_clear, fifo_full_int_clear
+    };
+    IntConfig1: IntConfig1 @ 0x0064, ReadWrite {
+        int_tpulse_duration, int_tdeassert_disable, int_async_reset
+    };
+    IntSource0: IntSource0 @ 0x0065, ReadWrite {
+        ui_fsync_int_1_en, pll_rdy_int_1_en, reset_down_int_1_en, ui_drdy_int_1_en,
+        fifo_ths_int_1_en, fifo_full_int_1_en, ui_agc_rdy_int_1_en
+    };
+    IntSource1: IntSource1 @ 0x0066, ReadWrite {
+        i_3_c_protocol_error_int_1_en, smd_int_1_en, wom_z_int_1_en, wom_y_int_1_en, wom_x_int_1_en
+    };
+    IntSource3: IntSource3 @ 0x0068, ReadWrite {
+        ui_fsync_int_2_en, pll_rdy_int_2_en, reset_down_int_2_en, ui_drdy_int_2_en,
+        fifo_ths_int_2_en, fifo_full_int_2_en, ui_agc_rdy_int_2_en
+    };
+    IntSource4: IntSource4 @ 0x0069, ReadWrite {
+        i_3_c_protocol_error_int_2_en, smd_int_2_en, wom_z_int_2_en, wom_y_int_2_en, wom_x_int_2_en
+    };
+    FifoLostPkt: FifoLostPkt @ 0x006c, ReadOnly { value };
+    SelfTestConfig: SelfTestConfig @ 0x0070, ReadWrite {
+        accel_st_power, en_az_st, en_ay_st, en_ax_st, en_gz_st, en_gy_st, en_gx_st
+    };
+    WhoAmI: WhoAmI @ 0x0075, ReadOnly { whoami };
+    SensorConfig: SensorConfig @ 0x0103, ReadWrite {
+        zg_disable, yg_disable, xg_disable, za_disable, ya_disable, xa_disable
+    };
+    GyroConfigStatic2: GyroConfigStatic2 @ 0x010b, ReadWrite { gyro_aaf_dis, gyro_nf_dis };
+    GyroAntiAliasFilterConfig: GyroAntiAliasFilterConfig @ 0x010c, ReadWrite {
+        gyro_aaf_delt, gyro_aaf_deltsqr, gyro_aaf_bitshift
+    };
+    GyroXNfCoswz: GyroXNfCoswz @ 0x010f, ReadWrite { value };
+    GyroYNfCoswz: GyroXNfCoswz @ 0x0110, ReadWrite { value };
+    GyroZNfCoswz: GyroXNfCoswz @ 0x0111, ReadWrite { value };
+    GyroConfigStatic9: GyroConfigStatic9 @ 0x0112, ReadWrite {
+        gyro_z_nf_coswz_sel, gyro_y_nf_coswz_sel, gyro_x_nf_coswz_sel, gyro_z_nf_coswz,
+        gyro_y_nf_coswz, gyro_x_nf_coswz
+    };
+    GyroConfigStatic10: GyroConfigStatic10 @ 0x0113, ReadWrite { gyro_nf_bw_sel };
+    XgStData: GyroXNfCoswz @ 0x015f, ReadWrite { value };
+    YgStData: GyroXNfCoswz @ 0x0160, ReadWrite { value };
+    ZgStData: GyroXNfCoswz @ 0x0161, ReadWrite { value };
+    TmstVal: TmstVal @ 0x0162, ReadWrite { value };
+    IntfConfig4: IntfConfig4 @ 0x017a, ReadWrite { i_3_c_bus_mode, spi_ap_4_wire };
+    IntfConfig5: IntfConfig5 @ 0x017b, ReadWrite { pin_9_function };
+    IntfConfig6: IntfConfig6 @ 0x017c, ReadWrite {
+        asynctime_0_dis, i_3_c_en, i_3_c_ibi_byte_en, i_3_c_ibi_en, i_3_c_ddr_en, i_3_c_sdr_en
+    };
+    AccelAntiAliasFilterConfig: AccelAntiAliasFilterConfig @ 0x020c, ReadWrite {
+        accel_aaf_dis, accel_aaf_delt, accel_aaf_deltsqr, accel_aaf_bitshift
+    };
+    XaStData: GyroXNfCoswz @ 0x023b, ReadWrite { value };
+    YaStData: GyroXNfCoswz @ 0x023c, ReadWrite { value };
+    ZaStData: GyroXNfCoswz @ 0x023d, ReadWrite { value };
+    ApexConfig1: ApexConfig1 @ 0x0440, ReadWrite { low_energy_amp_th_sel, dmp_power_save_time_sel };
+    ApexConfig2: ApexConfig2 @ 0x0441, ReadWrite { ped_amp_th_sel, ped_step_cnt_th_sel };
+    ApexConfig3: ApexConfig3 @ 0x0442, ReadWrite {
+        ped_step_det_th_sel, ped_sb_timer_th_sel, ped_hi_en_th_sel
+    };
+    ApexConfig4: ApexConfig4 @ 0x0443, ReadWrite { tilt_wait_time_sel, sleep_time_out };
+    ApexConfig5: ApexConfig5 @ 0x0444, ReadWrite { mounting_matrix };
+    ApexConfig6: ApexConfig6 @ 0x0445, ReadWrite { sleep_gesture_delay };
+    ApexConfig7: ApexConfig7 @ 0x0447, ReadWrite { tap_tmax, tap_tavg, tap_tmin };
+    ApexConfig8: ApexConfig8 @ 0x0448, ReadWrite { sensitivity_mode };
+    AccelWomXThr: AccelWomXThr @ 0x044a, ReadWrite { value };
+    AccelWomYThr: AccelWomXThr @ 0x044b, ReadWrite { value };
+    AccelWomZThr: AccelWomXThr @ 0x044c, ReadWrite { value };
+    IntSource6: IntSource6 @ 0x044d, ReadWrite {
+        step_det_int_1_en, step_cnt_ofl_int_1_en, tilt_det_int_1_en, wake_det_int_1_en,
+        sleep_det_int_1_en, tap_det_int_1_en
+    };
+    IntSource7: IntSource7 @ 0x044e, ReadWrite {
+        step_det_int_2_en, step_cnt_ofl_int_2_en, tilt_det_int_2_en, wake_det_int_2_en,
+        sleep_det_int_2_en, tap_det_int_2_en
+    };
+    IntSource8: IntSource8 @ 0x044f, ReadWrite {
+        fsync_ibi_en, pll_rdy_ibi_en, reset_down_ibi_en, ui_drdy_ibi_en, fifo_ths_ibi_en,
+        fifo_full_ibi_en, ui_agc_rdy_ibi_en
+    };
+    IntSource9: IntSource9 @ 0x0450, ReadWrite {
+        i_3_c_protocol_error_ibi_en, smd_ibi_en, wom_z_ibi_en, wom_y_ibi_en, wom_x_ibi_en
+    };
+    IntSource10: IntSource10 @ 0x0451, ReadWrite {
+        step_det_ibi_en, step_cnt_ofl_ibi_en, tilt_det_ibi_en, wake_det_ibi_en, sleep_det_ibi_en,
+        tap_det_ibi_en
+    };
+    OffsetUser0: OffsetUser0 @ 0x0477, ReadWrite { gyro_x_offuser_lo };
+    OffsetUser1: OffsetUser1 @ 0x0478, ReadWrite { gyro_y_offuser_hi, gyro_x_offuser_hi };
+    OffsetUser2: OffsetUser2 @ 0x0479, ReadWrite { gyro_y_offuser_lo };
+    OffsetUser3: OffsetUser3 @ 0x047a, ReadWrite { gyro_z_offuser_lo };
+    OffsetUser4: OffsetUser4 @ 0x047b, ReadWrite { accel_x_offuser_hi, gyro_z_offuser_hi };
+    OffsetUser5: OffsetUser5 @ 0x047c, ReadWrite { accel_x_offuser_lo };
+    OffsetUser6: OffsetUser6 @ 0x047d, ReadWrite { accel_y_offuser_lo };
+    OffsetUser7: OffsetUser7 @ 0x047e, ReadWrite { accel_z_offuser_hi, accel_y_offuser_hi };
+    OffsetUser8: OffsetUser8 @ 0x047f, ReadWrite { accel_z_offuser_lo };
+};
+
+/// Size of the largest register in bytes.
+pub(crate) const MAX_SIZE_BYTES: usize = {
+    let mut max = 0;
+    let mut i = 0;
+    while i < REGISTERS.len() {
+        if REGISTERS[i].size_bytes > max {
+            max = REGISTERS[i].size_bytes;
+        }
+        i += 1;
+    }
+    max
+};
+
+/// Address of the register called `name` in [`REGISTERS`]. Unknown names fail to compile.
+const fn address_of(name: &str) -> u16 {
+    let name = name.as_bytes();
+    let mut i = 0;
+    'registers: while i < REGISTERS.len() {
+        let candidate = REGISTERS[i].name.as_bytes();
+        i += 1;
+        if candidate.len() != name.len() {
+            continue;
+        }
+        let mut j = 0;
+        while j < name.len() {
+            if candidate[j] != name[j] {
+                continue 'registers;
+            }
+            j += 1;
+        }
+        return REGISTERS[i - 1].address;
+    }
+    panic!("unknown register");
+}
+
+// Addresses for code that accesses registers by address rather than through the generated
+// accessors, such as block transfers and the mock and simulated devices.
+pub(crate) const DEVICE_CONFIG: u16 = address_of("DeviceConfig");
+pub(crate) const TEMP_DATA: u16 = address_of("TempData");
+pub(crate) const FIFO_DATA: u16 = address_of("FifoData");
+pub(crate) const PWR_MGMT: u16 = address_of("PwrMgmt");
+pub(crate) const APEX_CONFIG: u16 = address_of("ApexConfig");
+pub(crate) const TMST_VAL: u16 = address_of("TmstVal");
+pub(crate) const OFFSET_USER0: u16 = address_of("OffsetUser0");
+
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_CONFIG: u16 = address_of("IntConfig");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const FIFO_CONFIG: u16 = address_of("FifoConfig");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const ACCEL_DATA_X: u16 = address_of("AccelDataX");
+#[cfg(test)]
+pub(crate) const TIMESTAMP_FSYNC: u16 = address_of("TimestampFsync");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_STATUS: u16 = address_of("IntStatus");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const FIFO_COUNT: u16 = address_of("FifoCount");
+#[cfg(test)]
+pub(crate) const INT_STATUS3: u16 = address_of("IntStatus3");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const SIGNAL_PATH_RESET: u16 = address_of("SignalPathReset");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INTF_CONFIG0: u16 = address_of("IntfConfig0");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const GYRO_CONFIG0: u16 = address_of("GyroConfig0");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const ACCEL_CONFIG0: u16 = address_of("AccelConfig0");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const TMST_CONFIG: u16 = address_of("TmstConfig");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const FIFO_CONFIG1: u16 = address_of("FifoConfig1");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const FIFO_WATERMARK: u16 = address_of("FifoWatermark");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_CONFIG0: u16 = address_of("IntConfig0");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_CONFIG1: u16 = address_of("IntConfig1");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_SOURCE0: u16 = address_of("IntSource0");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const INT_SOURCE3: u16 = address_of("IntSource3");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const FIFO_LOST_PKT: u16 = address_of("FifoLostPkt");
+#[cfg(any(test, feature = "mock"))]
+pub(crate) const SENSOR_CONFIG: u16 = address_of("SensorConfig");
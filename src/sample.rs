@@ -0,0 +1,152 @@
+//! Conversion of raw sensor data into physical units.
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::{AccelFullScale, DataRate, Error, FifoPacket, GyroFullScale, Icm42605};
+
+impl AccelFullScale {
+    /// Measurement range in g. Reserved values are treated as ±16 g.
+    pub const fn range_g(self) -> f32 {
+        match self {
+            Self::Max16G | Self::Reserved(_) => 16.0,
+            Self::Max8G => 8.0,
+            Self::Max4G => 4.0,
+            Self::Max2G => 2.0,
+        }
+    }
+
+    /// Sensitivity in LSB per g.
+    pub const fn lsb_per_g(self) -> f32 {
+        32768.0 / self.range_g()
+    }
+}
+
+impl GyroFullScale {
+    /// Measurement range in degrees per second.
+    pub const fn range_dps(self) -> f32 {
+        match self {
+            Self::DegreesPerSec2000 => 2000.0,
+            Self::DegreesPerSec1000 => 1000.0,
+            Self::DegreesPerSec500 => 500.0,
+            Self::DegreesPerSec250 => 250.0,
+            Self::DegreesPerSec125 => 125.0,
+            Self::DegreesPerSec62X5 => 62.5,
+            Self::DegreesPerSec31X25 => 31.25,
+            Self::DegreesPerSec15X625 => 15.625,
+        }
+    }
+
+    /// Sensitivity in LSB per degree per second.
+    pub const fn lsb_per_dps(self) -> f32 {
+        32768.0 / self.range_dps()
+    }
+}
+
+/// The full-scale ranges both sensors are configured with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FullScale {
+    pub accel: AccelFullScale,
+    pub gyro: GyroFullScale,
+}
+
+impl Default for FullScale {
+    /// The reset configuration, ±16 g and ±2000 °/s.
+    fn default() -> Self {
+        Self {
+            accel: AccelFullScale::Max16G,
+            gyro: GyroFullScale::DegreesPerSec2000,
+        }
+    }
+}
+
+impl FullScale {
+    /// Convert raw accelerometer values into g.
+    pub fn accel_g(&self, raw: [Option<i16>; 3]) -> [Option<f32>; 3] {
+        raw.map(|v| v.map(|v| f32::from(v) / self.accel.lsb_per_g()))
+    }
+
+    /// Convert raw gyroscope values into degrees per second.
+    pub fn gyro_dps(&self, raw: [Option<i16>; 3]) -> [Option<f32>; 3] {
+        raw.map(|v| v.map(|v| f32::from(v) / self.gyro.lsb_per_dps()))
+    }
+}
+
+/// The output data rates both sensors are configured with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DataRates {
+    pub accel: DataRate,
+    pub gyro: DataRate,
+}
+
+impl Default for DataRates {
+    /// The reset configuration, 1 kHz for both sensors.
+    fn default() -> Self {
+        Self {
+            accel: DataRate::Hz1000,
+            gyro: DataRate::Hz1000,
+        }
+    }
+}
+
+/// A sample converted into physical units.
+///
+/// Axes without valid data are `None`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScaledSample {
+    /// Acceleration in g.
+    pub accel: [Option<f32>; 3],
+    /// Angular rate in degrees per second.
+    pub gyro: [Option<f32>; 3],
+    /// Temperature in degrees Celsius.
+    pub temperature: f32,
+}
+
+impl ScaledSample {
+    /// The acceleration, if all three axes are valid.
+    pub fn accel_vector(&self) -> Option<[f32; 3]> {
+        let [x, y, z] = self.accel;
+        Some([x?, y?, z?])
+    }
+
+    /// The angular rate, if all three axes are valid.
+    pub fn gyro_vector(&self) -> Option<[f32; 3]> {
+        let [x, y, z] = self.gyro;
+        Some([x?, y?, z?])
+    }
+}
+
+impl FifoPacket {
+    /// Convert the packet into physical units using the configured full-scale ranges.
+    pub fn scaled(&self, full_scale: FullScale) -> ScaledSample {
+        ScaledSample {
+            accel: full_scale.accel_g(self.accel),
+            gyro: full_scale.gyro_dps(self.gyro),
+            temperature: self.temperature_celsius(),
+        }
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Read the configured full-scale ranges.
+    pub async fn read_full_scale(&mut self) -> Result<FullScale, Error<I::Error>> {
+        let accel = self.user_bank_0().accel_config_0().read_async().await?;
+        let gyro = self.user_bank_0().gyro_config_0().read_async().await?;
+        Ok(FullScale {
+            accel: accel.gyro_fs_sel(),
+            gyro: gyro.gyro_fs_sel(),
+        })
+    }
+
+    /// Read the configured output data rates.
+    pub async fn read_data_rates(&mut self) -> Result<DataRates, Error<I::Error>> {
+        let accel = self.user_bank_0().accel_config_0().read_async().await?;
+        let gyro = self.user_bank_0().gyro_config_0().read_async().await?;
+        Ok(DataRates {
+            accel: accel.gyro_odr(),
+            gyro: gyro.gyro_odr(),
+        })
+    }
+}
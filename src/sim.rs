@@ -0,0 +1,784 @@
+//! Behavioral simulation of the sensor, FIFO and interrupt outputs.
+//!
+//! A [`Simulator`] owns a [`MockDevice`] register file and generates samples from a
+//! [`MotionProfile`] at the configured output data rate. Its [`SimBus`] is used as the bus or
+//! register interface of the driver, [`SimPin`]s stand in for the INT1 and INT2 lines, and
+//! [`SimDelay`] lets delays advance simulated time instead of blocking.
+//!
+//! Simulated time only advances through [`Simulator::advance`], [`SimDelay`] and waits on a
+//! [`SimPin`].
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use crate::data_ready::SENSOR_DATA_LEN;
+use crate::mock::{MockDevice, MockError, RegisterSpace, impl_interfaces};
+use crate::registers::{
+    ACCEL_CONFIG0, ACCEL_DATA_X, DEVICE_CONFIG, FIFO_CONFIG, FIFO_CONFIG1, FIFO_COUNT, FIFO_DATA,
+    FIFO_LOST_PKT, FIFO_WATERMARK, GYRO_CONFIG0, INT_CONFIG, INT_CONFIG0, INT_CONFIG1, INT_SOURCE0,
+    INT_SOURCE3, INT_STATUS, INTF_CONFIG0, PWR_MGMT, SENSOR_CONFIG, SIGNAL_PATH_RESET, TEMP_DATA,
+    TMST_CONFIG, TMST_VAL,
+};
+use crate::{
+    AccelMode, DataRate, Endian, FifoCountRec, FifoMode, GyroMode, IntClearOption, InterruptPin,
+    field_sets,
+};
+
+/// FIFO capacity in bytes.
+const FIFO_SIZE: usize = 2048;
+/// Waiting on a [`SimPin`] gives up after this much simulated time without a level change.
+const WAIT_LIMIT_US: u64 = 10_000_000;
+
+/// `INT_STATUS` bits.
+const DATA_RDY_INT: u8 = 1 << 3;
+const FIFO_THS_INT: u8 = 1 << 2;
+const FIFO_FULL_INT: u8 = 1 << 1;
+
+/// FIFO header bits.
+const HEADER_ACCEL: u8 = 0x40;
+const HEADER_GYRO: u8 = 0x20;
+const HEADER_TMST_ODR: u8 = 0x08;
+const HEADER_ACCEL_ODR_CHANGED: u8 = 0x02;
+const HEADER_GYRO_ODR_CHANGED: u8 = 0x01;
+
+/// Physical quantities sensed at an instant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motion {
+    /// Acceleration in g.
+    pub accel: [f32; 3],
+    /// Angular rate in degrees per second.
+    pub gyro: [f32; 3],
+    /// Die temperature in degrees Celsius.
+    pub temperature: f32,
+}
+
+impl Default for Motion {
+    /// At rest, flat, at room temperature.
+    fn default() -> Self {
+        Self {
+            accel: [0.0, 0.0, 1.0],
+            gyro: [0.0; 3],
+            temperature: 25.0,
+        }
+    }
+}
+
+/// Source of the motion seen by the simulated sensor.
+pub trait MotionProfile {
+    /// The motion at `time_us` microseconds of simulated time.
+    fn motion(&mut self, time_us: u64) -> Motion;
+}
+
+impl<F: FnMut(u64) -> Motion> MotionProfile for F {
+    fn motion(&mut self, time_us: u64) -> Motion {
+        self(time_us)
+    }
+}
+
+/// A device lying still, see [`Motion::default`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stationary;
+
+impl MotionProfile for Stationary {
+    fn motion(&mut self, _time_us: u64) -> Motion {
+        Motion::default()
+    }
+}
+
+struct State<M> {
+    regs: MockDevice,
+    profile: M,
+    time_us: u64,
+    next_sample_us: Option<u64>,
+    /// Gyroscope and accelerometer data rates of the last sample.
+    last_odr: Option<(DataRate, DataRate)>,
+    last_tmst_ticks: u64,
+    fifo: VecDeque<u8>,
+    /// Unread lengths of the packets in `fifo`, which add up to its length. The first one may
+    /// be partially read.
+    packets: VecDeque<usize>,
+    lost_packets: u16,
+    /// Time of the last pulse on INT1 and INT2 in pulsed mode.
+    pulses: [Option<u64>; 2],
+}
+
+/// A simulated ICM-42605.
+pub struct Simulator<M> {
+    state: Rc<RefCell<State<M>>>,
+}
+
+impl<M: MotionProfile> Simulator<M> {
+    /// Create a freshly reset device at I2C address selected by `ad0`, sensing `profile`.
+    pub fn new(ad0: bool, profile: M) -> Self {
+        Self {
+            state: Rc::new(RefCell::new(State {
+                regs: MockDevice::new(ad0),
+                profile,
+                time_us: 0,
+                next_sample_us: None,
+                last_odr: None,
+                last_tmst_ticks: 0,
+                fifo: VecDeque::new(),
+                packets: VecDeque::new(),
+                lost_packets: 0,
+                pulses: [None; 2],
+            })),
+        }
+    }
+
+    /// A bus or register interface handle for the driver.
+    pub fn bus(&self) -> SimBus<M> {
+        SimBus {
+            state: self.state.clone(),
+        }
+    }
+
+    /// A handle to the INT1 or INT2 output.
+    pub fn pin(&self, int: InterruptPin) -> SimPin<M> {
+        SimPin {
+            state: self.state.clone(),
+            int,
+        }
+    }
+
+    /// A delay provider that advances simulated time.
+    pub fn delay(&self) -> SimDelay<M> {
+        SimDelay {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Simulated time in microseconds.
+    pub fn time_us(&self) -> u64 {
+        self.state.borrow().time_us
+    }
+
+    /// Advance simulated time by `us`, generating all samples due in that interval.
+    pub fn advance(&self, us: u64) {
+        let mut state = self.state.borrow_mut();
+        let until = state.time_us + us;
+        state.advance_to(until);
+    }
+
+    /// Number of bytes currently held in the FIFO.
+    pub fn fifo_len(&self) -> usize {
+        self.state.borrow().fifo.len()
+    }
+
+    /// Access the underlying register file, e.g. to inspect its transaction log.
+    pub fn with_registers<R>(&self, f: impl FnOnce(&mut MockDevice) -> R) -> R {
+        f(&mut self.state.borrow_mut().regs)
+    }
+}
+
+impl<M: MotionProfile> State<M> {
+    fn field<F: From<[u8; N]>, const N: usize>(&self, address: u16) -> F {
+        let mut bytes = [0; N];
+        self.regs.peek(address, &mut bytes);
+        F::from(bytes)
+    }
+
+    fn write_word(&mut self, address: u16, value: u16, endian: Endian) {
+        let bytes = match endian {
+            Endian::BigEndian => value.to_be_bytes(),
+            Endian::LittleEndian => value.to_le_bytes(),
+        };
+        self.regs.poke(address, &bytes);
+    }
+
+    /// Interval between samples, or `None` while both sensors are off.
+    ///
+    /// The simulator produces accelerometer and gyroscope samples together, so while the
+    /// gyroscope is on both sensors run at its data rate and the accelerometer data rate is only
+    /// used when the accelerometer runs alone. The device samples each sensor at its own rate.
+    fn sample_period_us(&self) -> Option<u64> {
+        let pwr: field_sets::PwrMgmt = self.field(PWR_MGMT);
+        let odr = if pwr.gyro_mode() == GyroMode::LowNoise {
+            self.field::<field_sets::GyroConfig0, 1>(GYRO_CONFIG0)
+                .gyro_odr()
+        } else if matches!(pwr.accel_mode(), AccelMode::LowPower | AccelMode::LowNoise) {
+            self.field::<field_sets::AccelConfig0, 1>(ACCEL_CONFIG0)
+                .gyro_odr()
+        } else {
+            return None;
+        };
+        Some(odr.period()?.as_micros() as u64)
+    }
+
+    fn advance_to(&mut self, until: u64) {
+        while let Some(period) = self.sample_period_us() {
+            let next = *self.next_sample_us.get_or_insert(self.time_us + period);
+            if next > until {
+                break;
+            }
+            self.time_us = next;
+            self.sample();
+            self.next_sample_us = Some(next + period);
+        }
+        if self.sample_period_us().is_none() {
+            self.next_sample_us = None;
+        }
+        self.time_us = until;
+    }
+
+    /// The time of the next event that may change an interrupt output.
+    fn next_event_us(&self) -> Option<u64> {
+        let pulse_end = self
+            .pulses
+            .iter()
+            .flatten()
+            .map(|start| start + self.pulse_us())
+            .filter(|&end| end > self.time_us)
+            .min();
+        let next_sample = self
+            .next_sample_us
+            .or_else(|| self.sample_period_us().map(|period| self.time_us + period));
+        match (pulse_end, next_sample) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn pulse_us(&self) -> u64 {
+        if self
+            .field::<field_sets::IntConfig1, 1>(INT_CONFIG1)
+            .int_tpulse_duration()
+        {
+            8
+        } else {
+            100
+        }
+    }
+
+    fn sample(&mut self) {
+        let motion = self.profile.motion(self.time_us);
+        let pwr: field_sets::PwrMgmt = self.field(PWR_MGMT);
+        let gyro_config: field_sets::GyroConfig0 = self.field(GYRO_CONFIG0);
+        let accel_config: field_sets::AccelConfig0 = self.field(ACCEL_CONFIG0);
+        let intf: field_sets::IntfConfig0 = self.field(INTF_CONFIG0);
+        let endian = intf.sensor_data_endian();
+
+        let accel_on = matches!(pwr.accel_mode(), AccelMode::LowPower | AccelMode::LowNoise);
+        let gyro_on = pwr.gyro_mode() == GyroMode::LowNoise;
+        let accel_lsb = accel_config.gyro_fs_sel().lsb_per_g();
+        let gyro_lsb = gyro_config.gyro_fs_sel().lsb_per_dps();
+        // Disabled axes output the invalid-data marker like a powered-off sensor.
+        let sensor: field_sets::SensorConfig = self.field(SENSOR_CONFIG);
+        let accel_axes = [
+            sensor.xa_disable(),
+            sensor.ya_disable(),
+            sensor.za_disable(),
+        ];
+        let gyro_axes = [
+            sensor.xg_disable(),
+            sensor.yg_disable(),
+            sensor.zg_disable(),
+        ];
+        let mut accel = [0; 3];
+        let mut gyro = [0; 3];
+        for axis in 0..3 {
+            accel[axis] = to_raw(
+                accel_on && !accel_axes[axis],
+                motion.accel[axis] * accel_lsb,
+            );
+            gyro[axis] = to_raw(gyro_on && !gyro_axes[axis], motion.gyro[axis] * gyro_lsb);
+        }
+
+        let temp = to_raw(true, (motion.temperature - 25.0) * 132.48);
+        self.write_word(TEMP_DATA, temp as u16, endian);
+        for (i, value) in accel.into_iter().chain(gyro).enumerate() {
+            self.write_word(ACCEL_DATA_X + 2 * i as u16, value as u16, endian);
+        }
+
+        let mut events = DATA_RDY_INT;
+        let odr = (gyro_config.gyro_odr(), accel_config.gyro_odr());
+        let mut odr_changed = 0;
+        if let Some((gyro_odr, accel_odr)) = self.last_odr.replace(odr) {
+            if accel_odr != odr.1 {
+                odr_changed |= HEADER_ACCEL_ODR_CHANGED;
+            }
+            if gyro_odr != odr.0 {
+                odr_changed |= HEADER_GYRO_ODR_CHANGED;
+            }
+        }
+        let fifo: field_sets::FifoConfig = self.field(FIFO_CONFIG);
+        if fifo.fifo_mode() != FifoMode::Bypass {
+            events |= self.push_packet(&motion, accel, gyro, odr_changed, endian);
+        }
+        self.raise(events);
+    }
+
+    /// Write a packet into the FIFO, returning the `INT_STATUS` bits it raises.
+    ///
+    /// `odr_changed` holds the header bits flagging a data rate change.
+    fn push_packet(
+        &mut self,
+        motion: &Motion,
+        accel: [i16; 3],
+        gyro: [i16; 3],
+        odr_changed: u8,
+        endian: Endian,
+    ) -> u8 {
+        let config: field_sets::FifoConfig1 = self.field(FIFO_CONFIG1);
+        let (with_accel, with_gyro) = (config.fifo_accel_en(), config.fifo_gyro_en());
+        if !with_accel && !with_gyro {
+            return 0;
+        }
+
+        let word = |value: u16| match endian {
+            Endian::BigEndian => value.to_be_bytes(),
+            Endian::LittleEndian => value.to_le_bytes(),
+        };
+        let mut header = odr_changed;
+        let mut packet = Vec::with_capacity(16);
+        packet.push(0);
+        if with_accel {
+            header |= HEADER_ACCEL;
+            accel.iter().for_each(|&v| packet.extend(word(v as u16)));
+        }
+        if with_gyro {
+            header |= HEADER_GYRO;
+            gyro.iter().for_each(|&v| packet.extend(word(v as u16)));
+        }
+        packet.push(to_raw(true, (motion.temperature - 25.0) * 2.07).clamp(-128, 127) as u8);
+        if with_accel && with_gyro {
+            header |= HEADER_TMST_ODR;
+            let tmst: field_sets::TmstConfig = self.field(TMST_CONFIG);
+            let ticks = self.time_us / if tmst.tmst_res() { 16 } else { 1 };
+            let value = if tmst.tmst_delta_en() {
+                ticks - self.last_tmst_ticks
+            } else {
+                ticks
+            };
+            self.last_tmst_ticks = ticks;
+            packet.extend(word(value as u16));
+        }
+        packet[0] = header;
+
+        let mut events = 0;
+        let previous = self.fifo_level();
+        if self.fifo.len() + packet.len() > FIFO_SIZE {
+            events |= FIFO_FULL_INT;
+            match self
+                .field::<field_sets::FifoConfig, 1>(FIFO_CONFIG)
+                .fifo_mode()
+            {
+                FifoMode::StopOnFull => {
+                    self.lost_packets = self.lost_packets.saturating_add(1);
+                    self.update_fifo_registers();
+                    return events;
+                }
+                _ => {
+                    while self.fifo.len() + packet.len() > FIFO_SIZE {
+                        let Some(len) = self.packets.pop_front() else {
+                            break;
+                        };
+                        let unread = len.min(self.fifo.len());
+                        self.fifo.drain(..unread);
+                        self.lost_packets = self.lost_packets.saturating_add(1);
+                    }
+                }
+            }
+        }
+        let len = packet.len();
+        self.packets.push_back(len);
+        self.fifo.extend(packet);
+        // Full once there is no room for another packet like this one.
+        if self.fifo.len() + len > FIFO_SIZE {
+            events |= FIFO_FULL_INT;
+        }
+
+        let watermark = u32::from(
+            self.field::<field_sets::FifoWatermark, 2>(FIFO_WATERMARK)
+                .value(),
+        );
+        let level = self.fifo_level();
+        let above = level >= watermark && watermark > 0;
+        if above && (previous < watermark || config.fifo_wm_gt_th()) {
+            events |= FIFO_THS_INT;
+        }
+        self.update_fifo_registers();
+        events
+    }
+
+    /// FIFO fill level in the unit selected by `FIFO_COUNT_REC`.
+    fn fifo_level(&self) -> u32 {
+        let intf: field_sets::IntfConfig0 = self.field(INTF_CONFIG0);
+        match intf.fifo_count_rec() {
+            FifoCountRec::Bytes => self.fifo.len() as u32,
+            FifoCountRec::Records => self.packets.len() as u32,
+        }
+    }
+
+    fn update_fifo_registers(&mut self) {
+        let intf: field_sets::IntfConfig0 = self.field(INTF_CONFIG0);
+        let count = self.fifo_level().min(u32::from(u16::MAX)) as u16;
+        self.write_word(FIFO_COUNT, count, intf.fifo_count_endian());
+        self.regs
+            .poke(FIFO_LOST_PKT, &self.lost_packets.to_le_bytes());
+    }
+
+    /// Latch `events` into `INT_STATUS` and pulse the interrupt outputs they are routed to.
+    fn raise(&mut self, events: u8) {
+        let mut status = [0];
+        self.regs.peek(INT_STATUS, &mut status);
+        self.regs.poke(INT_STATUS, &[status[0] | events]);
+
+        for (pin, source) in [(0, INT_SOURCE0), (1, INT_SOURCE3)] {
+            let mut enabled = [0];
+            self.regs.peek(source, &mut enabled);
+            if events & enabled[0] != 0 {
+                self.pulses[pin] = Some(self.time_us);
+            }
+        }
+    }
+
+    fn asserted(&self, int: InterruptPin) -> bool {
+        let config: field_sets::IntConfig = self.field(INT_CONFIG);
+        let (latched, active_high, source) = match int {
+            InterruptPin::Int1 => (config.int_1_mode(), config.int_1_polarity(), INT_SOURCE0),
+            InterruptPin::Int2 => (config.int_2_mode(), config.int_2_polarity(), INT_SOURCE3),
+        };
+
+        let asserted = if latched {
+            let (mut status, mut enabled) = ([0], [0]);
+            self.regs.peek(INT_STATUS, &mut status);
+            self.regs.peek(source, &mut enabled);
+            status[0] & enabled[0] & 0x7f != 0
+        } else {
+            self.pulses[int as usize].is_some_and(|start| self.time_us < start + self.pulse_us())
+        };
+        asserted == active_high
+    }
+
+    fn pop_fifo(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            let Some(next) = self.fifo.pop_front() else {
+                *byte = 0xff;
+                continue;
+            };
+            *byte = next;
+            // The front packet is now partially read; drop it once fully consumed.
+            if let Some(front) = self.packets.front_mut() {
+                *front -= 1;
+                if *front == 0 {
+                    self.packets.pop_front();
+                }
+            }
+        }
+        self.update_fifo_registers();
+    }
+
+    /// Clear `DATA_RDY_INT` if configured to clear on reading the sensor data registers.
+    fn sensor_data_read(&mut self) {
+        let config: field_sets::IntConfig0 = self.field(INT_CONFIG0);
+        if matches!(
+            config.ui_drdy_int_clear(),
+            IntClearOption::SensorRegisterRead | IntClearOption::Both
+        ) {
+            let mut status = [0];
+            self.regs.peek(INT_STATUS, &mut status);
+            self.regs.poke(INT_STATUS, &[status[0] & !DATA_RDY_INT]);
+        }
+    }
+
+    /// Clear the state kept outside the register file after the registers have been reset.
+    fn soft_reset(&mut self) {
+        self.next_sample_us = None;
+        self.last_odr = None;
+        self.last_tmst_ticks = 0;
+        self.fifo.clear();
+        self.packets.clear();
+        self.lost_packets = 0;
+        self.pulses = [None; 2];
+    }
+
+    fn signal_path_reset(&mut self) {
+        let spr: field_sets::SignalPathReset = self.field(SIGNAL_PATH_RESET);
+        if spr.fifo_flush() {
+            self.fifo.clear();
+            self.packets.clear();
+            self.update_fifo_registers();
+        }
+        if spr.tmst_strobe() {
+            let tmst: field_sets::TmstConfig = self.field(TMST_CONFIG);
+            let ticks = self.time_us / if tmst.tmst_res() { 16 } else { 1 };
+            let value = (ticks as u32 & 0xf_ffff).to_le_bytes();
+            self.regs.poke(TMST_VAL, &value[..3]);
+        }
+        self.regs.poke(SIGNAL_PATH_RESET, &[0]);
+    }
+}
+
+/// Bus and register interface of a [`Simulator`].
+pub struct SimBus<M> {
+    state: Rc<RefCell<State<M>>>,
+}
+
+impl<M: MotionProfile> RegisterSpace for SimBus<M> {
+    fn i2c_address(&self) -> u8 {
+        self.state.borrow().regs.i2c_address()
+    }
+
+    fn bank(&self) -> u8 {
+        self.state.borrow().regs.bank()
+    }
+
+    fn read(&mut self, bank: u8, reg: u8, data: &mut [u8]) -> Result<(), MockError> {
+        let mut state = self.state.borrow_mut();
+        if u16::from_be_bytes([bank, reg]) == FIFO_DATA {
+            state.pop_fifo(data);
+            return Ok(());
+        }
+        state.regs.read(bank, reg, data)?;
+        let start = u16::from_be_bytes([bank, reg]);
+        let sensor_data = TEMP_DATA..TEMP_DATA + SENSOR_DATA_LEN as u16;
+        if (start..start + data.len() as u16).any(|a| sensor_data.contains(&a)) {
+            state.sensor_data_read();
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bank: u8, reg: u8, data: &[u8]) -> Result<(), MockError> {
+        let mut state = self.state.borrow_mut();
+        state.regs.write(bank, reg, data)?;
+        let start = u16::from_be_bytes([bank, reg]);
+        if (start..start + data.len() as u16).contains(&SIGNAL_PATH_RESET) {
+            state.signal_path_reset();
+        }
+        let soft_reset = DEVICE_CONFIG
+            .checked_sub(start)
+            .and_then(|offset| data.get(usize::from(offset)));
+        if soft_reset.is_some_and(|config| config & 0x01 != 0) {
+            state.soft_reset();
+        }
+        Ok(())
+    }
+}
+
+impl_interfaces!(SimBus<M>, M: MotionProfile);
+
+/// A simulated interrupt output.
+///
+/// Waiting on the pin advances simulated time until the awaited level or edge occurs.
+pub struct SimPin<M> {
+    state: Rc<RefCell<State<M>>>,
+    int: InterruptPin,
+}
+
+impl<M: MotionProfile> SimPin<M> {
+    fn level(&self) -> bool {
+        self.state.borrow().asserted(self.int)
+    }
+
+    /// Advance simulated time until the level equals `high`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the level does not change within 10 s of simulated time.
+    fn wait_for_level(&mut self, high: bool) {
+        let mut state = self.state.borrow_mut();
+        let deadline = state.time_us + WAIT_LIMIT_US;
+        while state.asserted(self.int) != high {
+            match state.next_event_us() {
+                Some(next) if next <= deadline => state.advance_to(next),
+                _ => panic!("{:?} did not change level within 10 s", self.int),
+            }
+        }
+    }
+}
+
+impl<M> embedded_hal::digital::ErrorType for SimPin<M> {
+    type Error = Infallible;
+}
+
+impl<M: MotionProfile> embedded_hal::digital::InputPin for SimPin<M> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.level())
+    }
+}
+
+impl<M: MotionProfile> embedded_hal_async::digital::Wait for SimPin<M> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true);
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false);
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(false);
+        self.wait_for_level(true);
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_level(true);
+        self.wait_for_level(false);
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        let level = self.level();
+        self.wait_for_level(!level);
+        Ok(())
+    }
+}
+
+/// A delay provider that advances the simulated time of a [`Simulator`].
+pub struct SimDelay<M> {
+    state: Rc<RefCell<State<M>>>,
+}
+
+impl<M: MotionProfile> SimDelay<M> {
+    fn advance_ns(&mut self, ns: u32) {
+        let mut state = self.state.borrow_mut();
+        let until = state.time_us + u64::from(ns.div_ceil(1000));
+        state.advance_to(until);
+    }
+}
+
+impl<M: MotionProfile> embedded_hal::delay::DelayNs for SimDelay<M> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.advance_ns(ns);
+    }
+}
+
+impl<M: MotionProfile> embedded_hal_async::delay::DelayNs for SimDelay<M> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.advance_ns(ns);
+    }
+}
+
+fn to_raw(enabled: bool, value: f32) -> i16 {
+    if enabled {
+        value.clamp(-32768.0, 32767.0) as i16
+    } else {
+        i16::MIN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use device_driver::RegisterInterface;
+
+    use super::*;
+    use crate::Icm42605;
+
+    /// Stream the given sensors into the FIFO at 1 kHz.
+    fn stream(
+        sim: &Simulator<Stationary>,
+        accel: bool,
+        gyro: bool,
+    ) -> Icm42605<SimBus<Stationary>> {
+        let mut device = Icm42605::new(sim.bus());
+        device
+            .user_bank_0()
+            .fifo_config_1()
+            .write(|r| {
+                r.set_fifo_accel_en(accel);
+                r.set_fifo_gyro_en(gyro);
+            })
+            .unwrap();
+        device
+            .user_bank_0()
+            .fifo_config()
+            .write(|r| r.set_fifo_mode(FifoMode::Stream))
+            .unwrap();
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| {
+                if accel {
+                    r.set_accel_mode(AccelMode::LowNoise);
+                }
+                if gyro {
+                    r.set_gyro_mode(GyroMode::LowNoise);
+                }
+            })
+            .unwrap();
+        device
+    }
+
+    fn status(sim: &Simulator<Stationary>) -> u8 {
+        let mut status = [0];
+        sim.with_registers(|regs| regs.peek(INT_STATUS, &mut status));
+        status[0]
+    }
+
+    #[test]
+    fn fifo_full_is_raised_once_no_packet_fits() {
+        let sim = Simulator::new(false, Stationary);
+        let _device = stream(&sim, true, false);
+
+        sim.advance(255_000);
+        assert_eq!(sim.fifo_len(), FIFO_SIZE - 8);
+        assert_eq!(status(&sim) & FIFO_FULL_INT, 0);
+
+        sim.advance(1_000);
+        assert_eq!(sim.fifo_len(), FIFO_SIZE);
+        assert_ne!(status(&sim) & FIFO_FULL_INT, 0);
+    }
+
+    #[test]
+    fn odr_change_is_flagged_for_the_changed_sensor_only() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim, true, true);
+
+        sim.advance(1_000);
+        device
+            .user_bank_0()
+            .accel_config_0()
+            .modify(|r| r.set_gyro_odr(DataRate::Hz500))
+            .unwrap();
+        sim.advance(1_000);
+
+        let mut packets = [0; 32];
+        device
+            .interface()
+            .read_register(FIFO_DATA, 32 * 8, &mut packets)
+            .unwrap();
+        assert_eq!(packets[0] & 0x03, 0);
+        assert_eq!(packets[16] & 0x03, HEADER_ACCEL_ODR_CHANGED);
+    }
+
+    #[test]
+    fn partial_reads_consume_packets() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim, true, true);
+        device
+            .user_bank_0()
+            .intf_config_0()
+            .modify(|r| r.set_fifo_count_rec(FifoCountRec::Records))
+            .unwrap();
+        sim.advance(3_000);
+
+        let records = |device: &mut Icm42605<SimBus<Stationary>>| {
+            device.user_bank_0().fifo_count().read().unwrap().value()
+        };
+        assert_eq!(records(&mut device), 3);
+
+        let mut bytes = [0; 27];
+        device
+            .interface()
+            .read_register(FIFO_DATA, 5 * 8, &mut bytes[..5])
+            .unwrap();
+        assert_eq!(records(&mut device), 3);
+        device
+            .interface()
+            .read_register(FIFO_DATA, 27 * 8, &mut bytes)
+            .unwrap();
+        assert_eq!(records(&mut device), 1);
+        assert_eq!(sim.fifo_len(), 16);
+    }
+}
@@ -0,0 +1,319 @@
+//! Saving and restoring the device configuration.
+
+use core::fmt;
+
+use device_driver::AsyncRegisterInterface;
+use embedded_hal_async::delay::DelayNs;
+
+use crate::power::MODE_CHANGE_US;
+use crate::registers::{APEX_CONFIG, MAX_SIZE_BYTES, PWR_MGMT, TMST_VAL};
+use crate::{
+    AccelMode, Error, GyroMode, Icm42605, REGISTERS, RegisterAccess, RegisterInfo, RegisterValue,
+    field_sets,
+};
+
+/// `TMST_VAL` is writable but holds a counter value rather than configuration.
+const fn is_config(info: &RegisterInfo) -> bool {
+    matches!(info.access, RegisterAccess::ReadWrite) && info.address != TMST_VAL
+}
+
+/// Total size of the configuration registers in bytes.
+const DATA_LEN: usize = {
+    let mut len = 0;
+    let mut i = 0;
+    while i < REGISTERS.len() {
+        if is_config(&REGISTERS[i]) {
+            len += REGISTERS[i].size_bytes;
+        }
+        i += 1;
+    }
+    len
+};
+
+/// The contents of every configuration register, as captured by [`Icm42605::save_config`].
+///
+/// The serialized form is a version byte followed by the raw register bytes in address order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    data: [u8; DATA_LEN],
+}
+
+impl ConfigSnapshot {
+    /// Version of the serialized format, bumped whenever the set of registers changes.
+    pub const VERSION: u8 = 1;
+    /// Length of the serialized snapshot in bytes.
+    pub const ENCODED_LEN: usize = 1 + DATA_LEN;
+
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[0] = Self::VERSION;
+        bytes[1..].copy_from_slice(&self.data);
+        bytes
+    }
+
+    /// Deserialize a snapshot, or return `None` if the version or length does not match.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&version, data) = bytes.split_first()?;
+        if version != Self::VERSION {
+            return None;
+        }
+        Some(Self {
+            data: data.try_into().ok()?,
+        })
+    }
+
+    /// The captured register values, in address order.
+    pub fn values(&self) -> impl Iterator<Item = RegisterValue> + '_ {
+        let mut offset = 0;
+        REGISTERS
+            .iter()
+            .filter(|info| is_config(info))
+            .map(move |info| {
+                let mut bytes = [0; MAX_SIZE_BYTES];
+                bytes[..info.size_bytes]
+                    .copy_from_slice(&self.data[offset..offset + info.size_bytes]);
+                offset += info.size_bytes;
+                RegisterValue::new(info, bytes)
+            })
+    }
+
+    /// The registers that differ between `self` and `other`.
+    pub fn diff<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = RegisterChange> + 'a {
+        self.values()
+            .zip(other.values())
+            .filter(|(old, new)| old.bytes() != new.bytes())
+            .map(|(old, new)| RegisterChange { old, new })
+    }
+}
+
+/// A register whose value differs between two [`ConfigSnapshot`]s.
+///
+/// Formatting with `{}` lists only the fields that changed, e.g.
+/// `PwrMgmt: accel_mode: Off -> LowNoise`.
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterChange {
+    pub old: RegisterValue,
+    pub new: RegisterValue,
+}
+
+impl RegisterChange {
+    /// The bits that differ, with the first register byte in the most significant position.
+    pub fn changed_bits(&self) -> u32 {
+        self.old
+            .bytes()
+            .iter()
+            .zip(self.new.bytes())
+            .fold(0, |mask, (old, new)| (mask << 8) | u32::from(old ^ new))
+    }
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.old.info.name)?;
+        let (old, new) = (self.old.bytes(), self.new.bytes());
+        let changed = self
+            .old
+            .info
+            .fields
+            .iter()
+            .filter(|field| (field.changed)(old, new));
+        for (i, field) in changed.enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{}: ", field.name)?;
+            (field.fmt)(old, f)?;
+            f.write_str(" -> ")?;
+            (field.fmt)(new, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Read every configuration register.
+    pub async fn save_config(&mut self) -> Result<ConfigSnapshot, Error<I::Error>> {
+        let mut data = [0; DATA_LEN];
+        let mut offset = 0;
+        for info in REGISTERS.iter().filter(|info| is_config(info)) {
+            let bytes = &mut data[offset..offset + info.size_bytes];
+            self.interface()
+                .read_register(info.address, (info.size_bytes * 8) as u32, bytes)
+                .await?;
+            offset += info.size_bytes;
+        }
+        Ok(ConfigSnapshot { data })
+    }
+
+    /// Write every configuration register of `snapshot`, e.g. after a soft reset.
+    ///
+    /// Running sensors are turned off before the configuration is rewritten, so the gyroscope
+    /// must have been on for at least 45 ms. If the snapshot has APEX features enabled, the DMP
+    /// is reset before and initialized after the APEX registers are written, as when enabling
+    /// them, so that they run with the restored configuration. The power modes are restored
+    /// last, after which this waits for the device to accept further register writes.
+    pub async fn restore_config(
+        &mut self,
+        snapshot: &ConfigSnapshot,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<I::Error>> {
+        let running = self.user_bank_0().pwr_mgmt().read_async().await?;
+        if running.gyro_mode() != GyroMode::Off || running.accel_mode() != AccelMode::Off {
+            self.user_bank_0()
+                .pwr_mgmt()
+                .modify_async(|r| {
+                    r.set_gyro_mode(GyroMode::Off);
+                    r.set_accel_mode(AccelMode::Off);
+                })
+                .await?;
+            delay.delay_us(MODE_CHANGE_US).await;
+        }
+
+        let apex = snapshot
+            .values()
+            .find(|value| value.info.address == APEX_CONFIG)
+            .map(|value| field_sets::ApexConfig::from([value.bytes()[0]]));
+        let dmp_in_use = apex.is_some_and(|apex| {
+            apex.tap_enable() || apex.ped_enable() || apex.tilt_enable() || apex.r_2_w_en()
+        });
+        if dmp_in_use {
+            self.reset_dmp(delay).await?;
+        }
+
+        let mut power = None;
+        for value in snapshot.values() {
+            if value.info.address == PWR_MGMT {
+                power = Some(value);
+                continue;
+            }
+            self.write_value(&value).await?;
+        }
+        if dmp_in_use {
+            self.init_dmp(delay).await?;
+        }
+        if let Some(power) = power {
+            self.write_value(&power).await?;
+            delay.delay_us(MODE_CHANGE_US).await;
+        }
+        Ok(())
+    }
+
+    async fn write_value(&mut self, value: &RegisterValue) -> Result<(), Error<I::Error>> {
+        let bits = (value.info.size_bytes * 8) as u32;
+        self.interface()
+            .write_register(value.info.address, bits, value.bytes())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use std::string::ToString;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::mock::{Direction, MockDevice, Transaction};
+    use crate::{DataRate, TiltWaitTime};
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn restore_turns_sensors_off_before_reconfiguring() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        block_on(device.user_bank_0().pwr_mgmt().write_async(|r| {
+            r.set_gyro_mode(GyroMode::LowNoise);
+            r.set_accel_mode(AccelMode::LowNoise);
+        }))
+        .unwrap();
+        let snapshot = block_on(device.save_config()).unwrap();
+        device.interface().clear_transactions();
+
+        block_on(device.restore_config(&snapshot, &mut NoDelay)).unwrap();
+        let writes: Vec<_> = device
+            .interface()
+            .transactions()
+            .iter()
+            .filter(|t| t.direction == Direction::Write)
+            .map(|t| (t.bank, t.register, t.data.as_slice()))
+            .collect();
+        assert_eq!(writes.first(), Some(&(0, 0x4e, &[0x00][..])));
+        assert_eq!(writes.last(), Some(&(0, 0x4e, &[0x0f][..])));
+    }
+
+    #[test]
+    fn restore_reinitializes_the_dmp_around_the_apex_registers() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        block_on(device.enable_tilt(TiltWaitTime::Secs2, None, &mut NoDelay)).unwrap();
+        let snapshot = block_on(device.save_config()).unwrap();
+        device.interface().clear_transactions();
+
+        block_on(device.restore_config(&snapshot, &mut NoDelay)).unwrap();
+        let log = device.interface().transactions();
+        let position = |f: &dyn Fn(&Transaction) -> bool| {
+            log.iter()
+                .position(|t| t.direction == Direction::Write && f(t))
+                .unwrap()
+        };
+        let signal_path_reset = |t: &Transaction, bits: u8| {
+            (t.bank, t.register, t.data.as_slice()) == (0, 0x4b, &[bits][..])
+        };
+        let mem_reset = position(&|t| signal_path_reset(t, 1 << 5));
+        let first_bank_4 = position(&|t| t.bank == 4);
+        let apex_config = position(&|t| (t.bank, t.register) == (0, 0x56));
+        let init = position(&|t| signal_path_reset(t, 1 << 6));
+        let power = log
+            .iter()
+            .rposition(|t| (t.bank, t.register) == (0, 0x4e))
+            .unwrap();
+        assert!(mem_reset < apex_config && apex_config < first_bank_4);
+        assert!(first_bank_4 < init && init < power);
+    }
+
+    #[test]
+    fn restore_leaves_the_dmp_alone_without_apex_features() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        let snapshot = block_on(device.save_config()).unwrap();
+        device.interface().clear_transactions();
+
+        block_on(device.restore_config(&snapshot, &mut NoDelay)).unwrap();
+        let signal_path_resets = device
+            .interface()
+            .transactions()
+            .iter()
+            .filter(|t| (t.bank, t.register) == (0, 0x4b))
+            .count();
+        assert_eq!(signal_path_resets, 0);
+    }
+
+    #[test]
+    fn changes_list_only_the_changed_fields() {
+        let mut device = Icm42605::new(MockDevice::new(false));
+        let before = block_on(device.save_config()).unwrap();
+        block_on(device.user_bank_0().pwr_mgmt().modify_async(|r| {
+            r.set_accel_mode(AccelMode::LowNoise);
+            r.set_gyro_mode(GyroMode::Standby);
+        }))
+        .unwrap();
+        block_on(
+            device
+                .user_bank_0()
+                .accel_config_0()
+                .modify_async(|r| r.set_gyro_odr(DataRate::Hz50)),
+        )
+        .unwrap();
+        let after = block_on(device.save_config()).unwrap();
+
+        let changes: Vec<_> = before.diff(&after).map(|c| c.to_string()).collect();
+        assert_eq!(
+            changes,
+            [
+                "PwrMgmt: gyro_mode: Off -> Standby, accel_mode: Off -> LowNoise",
+                "AccelConfig0: gyro_odr: Hz1000 -> Hz50",
+            ]
+        );
+    }
+}
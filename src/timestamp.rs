@@ -0,0 +1,486 @@
+//! The on-chip timestamp counter.
+
+use device_driver::AsyncRegisterInterface;
+
+use crate::{DataRate, Error, FifoPacket, FifoTimestampKind, Icm42605};
+
+/// Width of the `TMST_VAL` counter.
+const TMST_VAL_BITS: u32 = 20;
+
+/// Resolution of the timestamp counter (`TMST_RES`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimestampResolution {
+    #[default]
+    Micros1,
+    Micros16,
+}
+
+impl TimestampResolution {
+    /// Duration of one counter tick in microseconds.
+    pub const fn micros(self) -> u32 {
+        match self {
+            Self::Micros1 => 1,
+            Self::Micros16 => 16,
+        }
+    }
+}
+
+/// Timestamp counter configuration (`TMST_CONFIG`).
+///
+/// The default matches the reset value of `TMST_CONFIG`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimestampConfig {
+    pub resolution: TimestampResolution,
+    /// Write timestamp deltas instead of absolute values into FIFO packets.
+    pub fifo_delta: bool,
+    /// Enable time-stamping of FSYNC events.
+    pub fsync: bool,
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            resolution: TimestampResolution::Micros1,
+            fifo_delta: false,
+            fsync: true,
+        }
+    }
+}
+
+/// Extends the 20-bit `TMST_VAL` counter into a monotonic 64-bit microsecond timestamp.
+///
+/// The counter wraps after 2^20 ticks (about 1.05 s at 1 µs resolution, 16.8 s at 16 µs), so
+/// [`TimestampClock::read_timestamp`] must be called at least once per wrap period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimestampClock {
+    resolution: TimestampResolution,
+    last: Option<u32>,
+    ticks: u64,
+}
+
+impl TimestampClock {
+    pub const fn new(resolution: TimestampResolution) -> Self {
+        Self {
+            resolution,
+            last: None,
+            ticks: 0,
+        }
+    }
+
+    pub const fn resolution(&self) -> TimestampResolution {
+        self.resolution
+    }
+
+    /// Strobe the counter into `TMST_VAL` and read it, returning microseconds since the first read.
+    ///
+    /// The time is only correct if the previous read was less than one wrap period ago; whole
+    /// wrap periods between two reads go unnoticed, so after 1.5 s at 1 µs resolution this
+    /// reports about 0.45 s.
+    pub async fn read_timestamp<I>(
+        &mut self,
+        device: &mut Icm42605<I>,
+    ) -> Result<u64, Error<I::Error>>
+    where
+        I: AsyncRegisterInterface<AddressType = u16>,
+    {
+        let raw = device.read_tmst_val().await?;
+        Ok(self.update(raw))
+    }
+
+    /// Account for a raw `TMST_VAL` reading, returning microseconds since the first reading.
+    pub fn update(&mut self, raw: u32) -> u64 {
+        let mask = (1 << TMST_VAL_BITS) - 1;
+        if let Some(last) = self.last {
+            self.ticks += u64::from(raw.wrapping_sub(last) & mask);
+        }
+        self.last = Some(raw & mask);
+        self.ticks * u64::from(self.resolution.micros())
+    }
+}
+
+/// Device time of a FIFO packet, as reported by [`FifoTimestampTracker::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoTimestamp {
+    /// Microseconds since the first timestamped packet.
+    pub micros: u64,
+    /// The accelerometer or gyroscope ODR changed with this packet.
+    pub odr_changed: bool,
+}
+
+/// Reconstructs a continuous microsecond timeline from the 16-bit timestamps of FIFO packets.
+///
+/// In absolute mode the FIFO timestamp wraps after 2^16 ticks (about 65 ms at 1 µs resolution),
+/// so at least one timestamped packet must be pushed per wrap period.
+///
+/// Packets without an ODR timestamp, such as those holding a single sensor or carrying an FSYNC
+/// delay in its place, are given the time of the previous packet plus the sample period. The
+/// period is that of the data rate set with [`FifoTimestampTracker::set_data_rate`], or else the
+/// interval between the last two timestamped packets; such packets get no time until either is
+/// known. Delta timestamps only cover the time since the previous sample, so in delta mode the
+/// estimated time of these packets is carried over into the timeline.
+///
+/// Both periods are forgotten after a packet flagged with an ODR change; call
+/// [`FifoTimestampTracker::set_data_rate`] with the new rate to keep timing untimestamped
+/// packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FifoTimestampTracker {
+    resolution: TimestampResolution,
+    delta: bool,
+    period_us: Option<u64>,
+    /// Sample period measured from the timestamps.
+    interval_us: Option<u64>,
+    /// Packets without an ODR timestamp since the last timestamped packet.
+    untimed: u32,
+    started: bool,
+    last: Option<u16>,
+    ticks: u64,
+    /// Time estimated from the data rate since the last timestamped packet.
+    estimated_us: u64,
+    /// Estimated time not covered by delta timestamps.
+    carried_us: u64,
+    host_offset: Option<i64>,
+}
+
+impl FifoTimestampTracker {
+    /// Create a tracker for FIFO packets written with the given `TMST_CONFIG`.
+    pub const fn new(config: TimestampConfig) -> Self {
+        Self {
+            resolution: config.resolution,
+            delta: config.fifo_delta,
+            period_us: None,
+            interval_us: None,
+            untimed: 0,
+            started: false,
+            last: None,
+            ticks: 0,
+            estimated_us: 0,
+            carried_us: 0,
+            host_offset: None,
+        }
+    }
+
+    /// Give packets without a timestamp the time of the previous packet plus the period of
+    /// `odr`, the rate of the sensors in the FIFO.
+    pub fn set_data_rate(&mut self, odr: DataRate) {
+        self.period_us = odr.period().map(|period| period.as_micros() as u64);
+    }
+
+    /// Device time of the most recent packet in microseconds.
+    pub fn micros(&self) -> Option<u64> {
+        self.started.then(|| self.device_micros())
+    }
+
+    /// Account for the next packet drained from the FIFO.
+    ///
+    /// Returns `None` for packets that carry no ODR timestamp while the sample period is unknown.
+    pub fn push(&mut self, packet: &FifoPacket) -> Option<FifoTimestamp> {
+        let raw = packet
+            .timestamp
+            .filter(|_| packet.header.timestamp() == FifoTimestampKind::Odr);
+
+        match (raw, self.last) {
+            (Some(raw), Some(last)) => {
+                let ticks = if self.delta {
+                    u64::from(raw)
+                } else {
+                    u64::from(raw.wrapping_sub(last))
+                };
+                self.ticks += ticks;
+                // A delta only spans the time since the previous sample, an absolute timestamp
+                // every sample since the previous timestamp.
+                let samples = if self.delta { 1 } else { self.untimed + 1 };
+                self.interval_us =
+                    Some(ticks * u64::from(self.resolution.micros()) / u64::from(samples));
+                if self.delta {
+                    self.carried_us += self.estimated_us;
+                }
+            }
+            (Some(_), None) if self.started => {
+                // Continue the estimated timeline, which has no reference timestamp.
+                let us = self.estimated_us + self.period_us.unwrap_or(0);
+                self.ticks += us / u64::from(self.resolution.micros());
+            }
+            (Some(_), None) => {}
+            (None, _) => {
+                self.untimed += 1;
+                let period = self.period_us.or(self.interval_us)?;
+                if self.started {
+                    self.estimated_us += period;
+                }
+            }
+        }
+        if raw.is_some() {
+            self.estimated_us = 0;
+            self.untimed = 0;
+            self.last = raw;
+        }
+        self.started = true;
+
+        let odr_changed = packet.header.accel_odr_changed() || packet.header.gyro_odr_changed();
+        if odr_changed {
+            self.period_us = None;
+            self.interval_us = None;
+        }
+        Some(FifoTimestamp {
+            micros: self.device_micros(),
+            odr_changed,
+        })
+    }
+
+    /// Correlate the device timeline with a host clock sample in microseconds.
+    ///
+    /// `host_micros` should be sampled at the watermark interrupt and this called after the
+    /// packets up to that interrupt have been pushed, so that the most recent packet is taken to
+    /// have been written at `host_micros`.
+    pub fn correlate(&mut self, host_micros: u64) {
+        if let Some(device) = self.micros() {
+            self.host_offset = Some(host_micros as i64 - device as i64);
+        }
+    }
+
+    /// Convert a device time returned by [`FifoTimestampTracker::push`] into host time.
+    ///
+    /// Returns `None` until [`FifoTimestampTracker::correlate`] has been called.
+    pub fn to_host(&self, timestamp: FifoTimestamp) -> Option<u64> {
+        self.host_offset
+            .map(|offset| (timestamp.micros as i64 + offset) as u64)
+    }
+
+    fn device_micros(&self) -> u64 {
+        self.ticks * u64::from(self.resolution.micros()) + self.carried_us + self.estimated_us
+    }
+}
+
+impl<I: AsyncRegisterInterface<AddressType = u16>> Icm42605<I> {
+    /// Strobe the counter into `TMST_VAL` and read its raw value.
+    pub(crate) async fn read_tmst_val(&mut self) -> Result<u32, Error<I::Error>> {
+        self.user_bank_0()
+            .signal_path_reset()
+            .write_async(|r| r.set_tmst_strobe(true))
+            .await?;
+        Ok(self.user_bank_1().tmst_val().read_async().await?.value())
+    }
+
+    /// Enable the timestamp counter and make it readable through `TMST_VAL`.
+    pub async fn configure_timestamp(
+        &mut self,
+        config: TimestampConfig,
+    ) -> Result<TimestampClock, Error<I::Error>> {
+        self.user_bank_0()
+            .tmst_config()
+            .modify_async(|r| {
+                r.set_tmst_en(true);
+                r.set_tmst_to_regs_en(true);
+                r.set_tmst_res(config.resolution == TimestampResolution::Micros16);
+                r.set_tmst_delta_en(config.fifo_delta);
+                r.set_tmst_fsync_en(config.fsync);
+            })
+            .await?;
+        Ok(TimestampClock::new(config.resolution))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::sim::{SimBus, Simulator, Stationary};
+    use crate::{AccelMode, FifoItem, FifoManager, FifoMode, GyroMode, InterruptPin};
+
+    /// Drain the FIFO and push every packet into `tracker`.
+    fn push_fifo(
+        sim: &Simulator<Stationary>,
+        device: &mut Icm42605<SimBus<Stationary>>,
+        tracker: &mut FifoTimestampTracker,
+    ) -> Vec<Option<FifoTimestamp>> {
+        let mut fifo = block_on(FifoManager::new(sim.delay(), device)).unwrap();
+        let mut buf = [0; 2048];
+        let mut timestamps = Vec::new();
+        block_on(fifo.drain(device, &mut buf, |item| {
+            if let FifoItem::Packet(packet) = item {
+                timestamps.push(tracker.push(&packet));
+            }
+        }))
+        .unwrap();
+        timestamps
+    }
+
+    /// Stream the given sensors into the FIFO at 1 kHz.
+    fn stream(sim: &Simulator<Stationary>, gyro: bool) -> Icm42605<SimBus<Stationary>> {
+        let mut device = Icm42605::new(sim.bus());
+        device
+            .user_bank_0()
+            .fifo_config_1()
+            .write(|r| {
+                r.set_fifo_accel_en(true);
+                r.set_fifo_gyro_en(gyro);
+            })
+            .unwrap();
+        device
+            .user_bank_0()
+            .fifo_config()
+            .write(|r| r.set_fifo_mode(FifoMode::Stream))
+            .unwrap();
+        device
+            .user_bank_0()
+            .pwr_mgmt()
+            .write(|r| {
+                r.set_accel_mode(AccelMode::LowNoise);
+                if gyro {
+                    r.set_gyro_mode(GyroMode::LowNoise);
+                }
+            })
+            .unwrap();
+        device
+    }
+
+    fn micros(timestamps: &[Option<FifoTimestamp>]) -> Vec<u64> {
+        timestamps.iter().map(|t| t.unwrap().micros).collect()
+    }
+
+    #[test]
+    fn fifo_timestamps_are_unwrapped_in_both_modes() {
+        for fifo_delta in [false, true] {
+            let sim = Simulator::new(false, Stationary);
+            let mut device = stream(&sim, true);
+            let config = TimestampConfig {
+                fifo_delta,
+                ..TimestampConfig::default()
+            };
+            block_on(device.configure_timestamp(config)).unwrap();
+            let mut tracker = FifoTimestampTracker::new(config);
+
+            let mut all = Vec::new();
+            // 200 ms spans three wraps of the 16-bit FIFO timestamp.
+            for _ in 0..10 {
+                sim.advance(20_000);
+                all.extend(micros(&push_fifo(&sim, &mut device, &mut tracker)));
+            }
+            assert_eq!(all.len(), 200);
+            assert!(all.iter().enumerate().all(|(i, &t)| t == i as u64 * 1_000));
+        }
+    }
+
+    #[test]
+    fn odr_change_resets_the_sample_period() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim, false);
+        let mut tracker = FifoTimestampTracker::new(TimestampConfig::default());
+        tracker.set_data_rate(DataRate::Hz1000);
+
+        sim.advance(3_000);
+        assert_eq!(
+            micros(&push_fifo(&sim, &mut device, &mut tracker)),
+            [0, 1_000, 2_000]
+        );
+
+        device
+            .user_bank_0()
+            .accel_config_0()
+            .modify(|r| r.set_gyro_odr(DataRate::Hz500))
+            .unwrap();
+        sim.advance(6_000);
+        let timestamps = push_fifo(&sim, &mut device, &mut tracker);
+        assert_eq!(timestamps.len(), 3);
+        assert!(timestamps[0].unwrap().odr_changed);
+        // Packets after the change get no time until the new rate is known.
+        assert_eq!(timestamps[1..], [None, None]);
+
+        tracker.set_data_rate(DataRate::Hz500);
+        sim.advance(4_000);
+        let last = tracker.micros().unwrap();
+        assert_eq!(
+            micros(&push_fifo(&sim, &mut device, &mut tracker)),
+            [last + 2_000, last + 4_000]
+        );
+    }
+
+    #[test]
+    fn watermark_sample_maps_device_time_to_host_time() {
+        const HOST_START_US: u64 = 1_000_000_000;
+
+        let sim = Simulator::new(false, Stationary);
+        let mut device = stream(&sim, true);
+        let mut tracker = FifoTimestampTracker::new(TimestampConfig::default());
+        block_on(device.configure_timestamp(TimestampConfig::default())).unwrap();
+        device
+            .user_bank_0()
+            .int_config()
+            .write(|r| {
+                r.set_int_1_mode(true);
+                r.set_int_1_polarity(true);
+            })
+            .unwrap();
+        device
+            .user_bank_0()
+            .int_source_0()
+            .write(|r| r.set_fifo_ths_int_1_en(true))
+            .unwrap();
+        device
+            .user_bank_0()
+            .fifo_watermark()
+            .write(|r| r.set_value(10 * 16))
+            .unwrap();
+
+        let mut pin = sim.pin(InterruptPin::Int1);
+        block_on(device.wait_for_interrupt(InterruptPin::Int1, &mut pin)).unwrap();
+        let host_us = HOST_START_US + sim.time_us();
+        let timestamps = push_fifo(&sim, &mut device, &mut tracker);
+        assert_eq!(timestamps.len(), 10);
+        tracker.correlate(host_us);
+
+        for (i, timestamp) in timestamps.iter().enumerate() {
+            let age_us = (9 - i as u64) * 1_000;
+            assert_eq!(tracker.to_host(timestamp.unwrap()), Some(host_us - age_us));
+        }
+    }
+
+    #[test]
+    fn clock_extends_the_counter_across_wraps() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let mut clock = block_on(device.configure_timestamp(TimestampConfig::default())).unwrap();
+
+        sim.advance(100);
+        assert_eq!(block_on(clock.read_timestamp(&mut device)), Ok(0));
+        for expected in [800_000, 1_600_000, 2_400_000] {
+            sim.advance(800_000);
+            assert_eq!(block_on(clock.read_timestamp(&mut device)), Ok(expected));
+        }
+
+        // A gap of more than one wrap period loses the whole periods.
+        sim.advance(1_500_000);
+        let lost = 1 << TMST_VAL_BITS;
+        assert_eq!(
+            block_on(clock.read_timestamp(&mut device)),
+            Ok(3_900_000 - lost)
+        );
+    }
+
+    #[test]
+    fn clock_scales_16_us_ticks() {
+        let sim = Simulator::new(false, Stationary);
+        let mut device = Icm42605::new(sim.bus());
+        let config = TimestampConfig {
+            resolution: TimestampResolution::Micros16,
+            ..TimestampConfig::default()
+        };
+        let mut clock = block_on(device.configure_timestamp(config)).unwrap();
+        assert_eq!(clock.resolution(), TimestampResolution::Micros16);
+
+        assert_eq!(block_on(clock.read_timestamp(&mut device)), Ok(0));
+        // 20 s is more than one wrap period at 1 µs resolution, but not at 16 µs.
+        for expected in [10_000_000, 20_000_000] {
+            sim.advance(10_000_000);
+            assert_eq!(block_on(clock.read_timestamp(&mut device)), Ok(expected));
+        }
+    }
+}